@@ -0,0 +1,354 @@
+use glm::{lerp, make_vec3, mat4_to_mat3, quat_dot, quat_normalize, quat_slerp, quat_to_mat4, scaling, translation, vec4, Mat4, Quat, Vec3};
+
+use crate::{mesh::Mesh, scene::Scene};
+
+// Translation, rotation and scale of a node or joint relative to its parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::zeros(),
+            rotation: Quat::identity(),
+            scale: Vec3::repeat(1.0)
+        }
+    }
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Mat4 {
+        translation(&self.translation) * quat_to_mat4(&self.rotation) * scaling(&self.scale)
+    }
+
+    // Linear for translation and scale, spherical for rotation.
+    pub fn lerp(&self, other: &Self, amount: f32) -> Self {
+        Self {
+            translation: lerp(&self.translation, &other.translation, amount),
+            rotation: slerp(&self.rotation, &other.rotation, amount),
+            scale: lerp(&self.scale, &other.scale, amount)
+        }
+    }
+}
+
+// Slerp along the shorter arc, q and -q are the same rotation.
+pub fn slerp(a: &Quat, b: &Quat, amount: f32) -> Quat {
+    let b = if quat_dot(a, b) < 0.0 { -b } else { *b };
+
+    // Nearly equal rotations would divide by a tiny sine.
+    if quat_dot(a, &b) > 0.9995 {
+        return quat_normalize(&(a * (1.0 - amount) + b * amount));
+    }
+
+    quat_slerp(a, &b, amount)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    // Every keyframe stores an in tangent, the value and an out tangent.
+    CubicSpline
+}
+
+#[derive(Debug, Clone)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+    // Morph target weights, `times.len()` groups of one weight per target.
+    Weights(Vec<f32>)
+}
+
+// Animates one property of one node.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub node: usize,
+    // Seconds, ascending.
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+    pub interpolation: Interpolation
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub name: String,
+    pub channels: Vec<Channel>,
+    // Seconds, the last keyframe of any channel.
+    pub duration: f32
+}
+
+impl Channel {
+    // Keyframe before `time` and how far `time` is towards the next one, clamped to the first and last keyframe.
+    fn locate(&self, time: f32) -> (usize, usize, f32, f32) {
+        let next = self.times.partition_point(|t| *t <= time);
+
+        if next == 0 || next == self.times.len() {
+            let i = next.min(self.times.len() - 1);
+            return (i, i, 0.0, 0.0);
+        }
+
+        let span = self.times[next] - self.times[next - 1];
+        (next - 1, next, (time - self.times[next - 1]) / span.max(f32::EPSILON), span)
+    }
+
+    fn sample_vec3(&self, values: &[Vec3], time: f32) -> Vec3 {
+        let (a, b, t, span) = self.locate(time);
+
+        match self.interpolation {
+            Interpolation::Step => values[a],
+            Interpolation::Linear => lerp(&values[a], &values[b], t),
+            Interpolation::CubicSpline => {
+                let [h0, h1, h2, h3] = hermite(t, span);
+                values[3 * a + 1] * h0 + values[3 * a + 2] * h1 + values[3 * b + 1] * h2 + values[3 * b] * h3
+            }
+        }
+    }
+
+    fn sample_quat(&self, values: &[Quat], time: f32) -> Quat {
+        let (a, b, t, span) = self.locate(time);
+
+        match self.interpolation {
+            Interpolation::Step => values[a],
+            Interpolation::Linear => slerp(&values[a], &values[b], t),
+            Interpolation::CubicSpline => {
+                let [h0, h1, h2, h3] = hermite(t, span);
+                let q = values[3 * a + 1].coords * h0 + values[3 * a + 2].coords * h1 + values[3 * b + 1].coords * h2 + values[3 * b].coords * h3;
+
+                quat_normalize(&Quat::from(q))
+            }
+        }
+    }
+
+    fn sample_weights(&self, values: &[f32], time: f32) -> Vec<f32> {
+        let (a, b, t, span) = self.locate(time);
+
+        match self.interpolation {
+            Interpolation::Step | Interpolation::Linear => {
+                let count = values.len() / self.times.len();
+                let t = if self.interpolation == Interpolation::Step { 0.0 } else { t };
+
+                (0..count).map(|i| values[a * count + i] + (values[b * count + i] - values[a * count + i]) * t).collect()
+            },
+            Interpolation::CubicSpline => {
+                let count = values.len() / self.times.len() / 3;
+                let [h0, h1, h2, h3] = hermite(t, span);
+                let get = |keyframe: usize, part: usize, i: usize| values[(3 * keyframe + part) * count + i];
+
+                (0..count).map(|i| get(a, 1, i) * h0 + get(a, 2, i) * h1 + get(b, 1, i) * h2 + get(b, 0, i) * h3).collect()
+            }
+        }
+    }
+
+    // Writes the channel's value at `time` into the pose.
+    pub fn apply(&self, time: f32, pose: &mut Pose) {
+        if self.node >= pose.transforms.len() || self.times.is_empty() {
+            return;
+        }
+
+        let transform = &mut pose.transforms[self.node];
+
+        match &self.keyframes {
+            Keyframes::Translation(values) => transform.translation = self.sample_vec3(values, time),
+            Keyframes::Rotation(values) => transform.rotation = self.sample_quat(values, time),
+            Keyframes::Scale(values) => transform.scale = self.sample_vec3(values, time),
+            Keyframes::Weights(values) => {
+                if let Some(weights) = pose.weights.get_mut(self.node) {
+                    *weights = self.sample_weights(values, time);
+                }
+            }
+        }
+    }
+}
+
+// Cubic Hermite basis for the value, out tangent, next value and in tangent, with tangents
+// scaled by the keyframe spacing as glTF defines it.
+fn hermite(t: f32, span: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    [2.0 * t3 - 3.0 * t2 + 1.0, span * (t3 - 2.0 * t2 + t), -2.0 * t3 + 3.0 * t2, span * (t3 - t2)]
+}
+
+impl Animation {
+    pub fn new(name: String, channels: Vec<Channel>) -> Self {
+        let duration = channels.iter()
+            .filter_map(|c| c.times.last().copied())
+            .fold(0.0, f32::max);
+
+        Self { name, channels, duration }
+    }
+
+    // Poses the animated nodes at `time` seconds, other nodes keep their transform.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in &self.channels {
+            channel.apply(time, pose);
+        }
+    }
+
+    // Like `sample`, repeating the clip.
+    pub fn sample_looped(&self, time: f32, pose: &mut Pose) {
+        self.sample(if self.duration > 0.0 { time.rem_euclid(self.duration) } else { 0.0 }, pose);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub rest: Transform
+}
+
+// Joint hierarchy, animation channels and skins refer to joints by index.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    // Parents come before their children.
+    order: Vec<usize>
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>) -> Self {
+        let mut order = joints.iter().enumerate()
+            .filter(|(_, j)| j.parent.is_none())
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        let mut next = 0;
+
+        while next < order.len() {
+            let parent = order[next];
+            order.extend((0..joints.len()).filter(|i| joints[*i].parent == Some(parent)));
+            next += 1;
+        }
+
+        Self { joints, order }
+    }
+
+    // Every node of the scene is a joint, so animations and skins can use node indices as joint indices.
+    pub fn from_scene(scene: &Scene) -> Self {
+        Self::new(scene.nodes.iter().map(|node| Joint {
+            name: node.name.clone(),
+            parent: node.parent,
+            rest: node.transform
+        }).collect())
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|j| j.name == name)
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            transforms: self.joints.iter().map(|j| j.rest).collect(),
+            weights: vec![Vec::new(); self.joints.len()]
+        }
+    }
+
+    // Joint to model space transform of every joint.
+    pub fn world_matrices(&self, pose: &Pose) -> Vec<Mat4> {
+        let mut matrices = vec![Mat4::identity(); self.joints.len()];
+
+        for i in &self.order {
+            let local = pose.transforms[*i].matrix();
+
+            matrices[*i] = match self.joints[*i].parent {
+                Some(parent) => matrices[parent] * local,
+                None => local
+            };
+        }
+
+        matrices
+    }
+}
+
+// Local transforms of all joints of a skeleton.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose {
+    pub transforms: Vec<Transform>,
+    // Morph target weights of the mesh on each joint, empty keeps the mesh's own weights.
+    pub weights: Vec<Vec<f32>>
+}
+
+impl Pose {
+    // Crossfades between two poses of the same skeleton, `amount` 0 is `self` and 1 is `other`.
+    pub fn blend(&self, other: &Pose, amount: f32) -> Pose {
+        Pose {
+            transforms: self.transforms.iter().zip(&other.transforms)
+                .map(|(a, b)| a.lerp(b, amount))
+                .collect(),
+            weights: self.weights.iter().zip(&other.weights)
+                .map(|(a, b)| blend_weights(a, b, amount))
+                .collect()
+        }
+    }
+
+    // Blends in `other` only for the given joints, e.g. a wave on the arm of a walking character.
+    pub fn blend_joints(&mut self, other: &Pose, joints: &[usize], amount: f32) {
+        let count = self.transforms.len().min(other.transforms.len());
+
+        for i in joints.iter().filter(|i| **i < count) {
+            self.transforms[*i] = self.transforms[*i].lerp(&other.transforms[*i], amount);
+            self.weights[*i] = blend_weights(&self.weights[*i], &other.weights[*i], amount);
+        }
+    }
+}
+
+// Weights only blend if both poses set them, otherwise the set ones win.
+fn blend_weights(a: &[f32], b: &[f32], amount: f32) -> Vec<f32> {
+    match (a.is_empty(), b.is_empty()) {
+        (false, false) => a.iter().zip(b).map(|(a, b)| a + (b - a) * amount).collect(),
+        (true, _) => b.to_vec(),
+        (false, true) => a.to_vec()
+    }
+}
+
+// Joints of a skinned mesh, as node indices of the scene they were loaded with.
+#[derive(Debug, Clone)]
+pub struct Skin {
+    pub name: String,
+    pub joints: Vec<usize>,
+    // Model space to joint space in bind pose, one per joint.
+    pub inverse_bind_matrices: Vec<Mat4>,
+    pub skeleton: Option<usize>
+}
+
+impl Skin {
+    // Bind pose model space to posed model space for every joint, from the world matrices of the skeleton.
+    pub fn joint_matrices(&self, world_matrices: &[Mat4]) -> Vec<Mat4> {
+        self.joints.iter().zip(&self.inverse_bind_matrices)
+            .map(|(joint, inverse_bind)| world_matrices[*joint] * inverse_bind)
+            .collect()
+    }
+}
+
+// Linear blend skinning: every vertex is moved by the weighted sum of the matrices of up to four joints.
+// Returns a copy with the skinned positions and normals, unskinned meshes are returned unchanged.
+pub fn skin_mesh(mesh: &Mesh, joint_matrices: &[Mat4]) -> Mesh {
+    let mut skinned = mesh.clone();
+
+    if mesh.joints.is_empty() || mesh.weights.len() != mesh.joints.len() {
+        return skinned;
+    }
+
+    for (i, (joints, weights)) in mesh.joints.chunks_exact(4).zip(mesh.weights.chunks_exact(4)).enumerate() {
+        let matrix = joints.iter().zip(weights)
+            .filter(|(j, w)| **w != 0.0 && (**j as usize) < joint_matrices.len())
+            .fold(Mat4::zeros(), |sum, (j, w)| sum + joint_matrices[*j as usize] * *w);
+
+        let position = matrix * vec4(mesh.positions[3 * i], mesh.positions[3 * i + 1], mesh.positions[3 * i + 2], 1.0);
+        skinned.positions[3 * i..3 * i + 3].copy_from_slice(position.xyz().as_slice());
+
+        if let Some(normal) = mesh.normals.get(3 * i..3 * i + 3) {
+            let normal = (mat4_to_mat3(&matrix) * make_vec3(normal)).try_normalize(f32::EPSILON).unwrap_or_else(Vec3::zeros);
+            skinned.normals[3 * i..3 * i + 3].copy_from_slice(normal.as_slice());
+        }
+    }
+
+    skinned.update_bounds();
+    skinned
+}
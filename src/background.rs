@@ -0,0 +1,64 @@
+use glm::{vec2, vec4, vec4_to_vec3, Mat4, Vec3};
+
+use crate::{char::CharColor, color::LinearColor, texture::Texture};
+
+// Six faces in the order +X, -X, +Y, -Y, +Z, -Z, laid out like OpenGL cube maps.
+#[derive(Debug, Clone)]
+pub struct CubeMap {
+    pub faces: [Texture; 6]
+}
+
+impl CubeMap {
+    pub fn new(faces: [Texture; 6]) -> Self {
+        Self { faces }
+    }
+
+    pub fn sample(&self, dir: &Vec3) -> CharColor {
+        let abs = dir.abs();
+
+        // Pick the face of the major axis and project the other two components onto it.
+        let (face, sc, tc, ma) = if abs.x >= abs.y && abs.x >= abs.z {
+            if dir.x > 0.0 { (0, -dir.z, -dir.y, abs.x) } else { (1, dir.z, -dir.y, abs.x) }
+        } else if abs.y >= abs.z {
+            if dir.y > 0.0 { (2, dir.x, dir.z, abs.y) } else { (3, dir.x, -dir.z, abs.y) }
+        } else if dir.z > 0.0 {
+            (4, dir.x, -dir.y, abs.z)
+        } else {
+            (5, -dir.x, -dir.y, abs.z)
+        };
+
+        self.faces[face].sample(&vec2((sc / ma + 1.0) / 2.0, (tc / ma + 1.0) / 2.0))
+    }
+}
+
+// What is drawn behind the geometry, everywhere the depth buffer is untouched.
+#[derive(Debug, Clone)]
+pub enum Background {
+    // Leaves the terminal's own background visible.
+    None,
+    Solid(CharColor),
+    // Interpolated in linear space from the first to the last row of the viewport.
+    VerticalGradient { top: CharColor, bottom: CharColor },
+    Skybox(Box<CubeMap>)
+}
+
+impl Background {
+    // Color for a sample at normalized viewport coordinates `y` ([0, 1], top to bottom) looking along `dir`.
+    pub fn color(&self, y: f32, dir: impl FnOnce() -> Vec3) -> Option<CharColor> {
+        match self {
+            Background::None => None,
+            Background::Solid(color) => Some(*color),
+            Background::VerticalGradient { top, bottom } =>
+                Some(LinearColor::from(*top).lerp(&LinearColor::from(*bottom), y).into()),
+            Background::Skybox(cube_map) => Some(cube_map.sample(&dir()))
+        }
+    }
+}
+
+// World space view direction through a point in NDC, using the inverse view projection matrix.
+pub fn view_direction(inv_vp_matrix: &Mat4, x: f32, y: f32) -> Vec3 {
+    let near = inv_vp_matrix * vec4(x, y, -1.0, 1.0);
+    let far = inv_vp_matrix * vec4(x, y, 1.0, 1.0);
+
+    (vec4_to_vec3(&far) / far.w - vec4_to_vec3(&near) / near.w).normalize()
+}
@@ -0,0 +1,173 @@
+use glm::{make_vec3, vec3, vec4, vec4_to_vec3, Mat4, Vec3};
+
+// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    // Box around a flat list of xyz positions, empty lists give a box at the origin.
+    pub fn from_positions(positions: &[f32]) -> Self {
+        if positions.len() < 3 {
+            return Self::new(Vec3::zeros(), Vec3::zeros());
+        }
+
+        positions.chunks(3).map(make_vec3).fold(
+            Self::new(Vec3::repeat(f32::INFINITY), Vec3::repeat(f32::NEG_INFINITY)),
+            |aabb, p| Self::new(aabb.min.inf(&p), aabb.max.sup(&p)))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    // Half the size along each axis.
+    pub fn extents(&self) -> Vec3 {
+        (self.max - self.min) / 2.0
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self::new(self.min.inf(&other.min), self.max.sup(&other.max))
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i])
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] <= other.min[i] && other.max[i] <= self.max[i])
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.max - self.min;
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    // Box around the transformed box (Arvo's method), without transforming all 8 corners.
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+        let translation = vec4_to_vec3(&matrix.column(3).into());
+        let mut min = translation;
+        let mut max = translation;
+
+        for i in 0..3 {
+            for j in 0..3 {
+                let a = matrix[(i, j)] * self.min[j];
+                let b = matrix[(i, j)] * self.max[j];
+
+                min[i] += a.min(b);
+                max[i] += a.max(b);
+            }
+        }
+
+        Self::new(min, max)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    // Sphere around the centroid of a flat list of xyz positions.
+    pub fn from_positions(positions: &[f32]) -> Self {
+        let count = (positions.len() / 3).max(1) as f32;
+        let center = positions.chunks(3).map(make_vec3).sum::<Vec3>() / count;
+        let radius = positions.chunks(3).map(|p| (make_vec3(p) - center).norm()).fold(0.0, f32::max);
+
+        Self::new(center, radius)
+    }
+
+    // Conservative for non-uniform scaling: the radius grows by the largest axis scale.
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+        let center = matrix * vec4(self.center.x, self.center.y, self.center.z, 1.0);
+        let scale = (0..3)
+            .map(|i| vec3(matrix[(0, i)], matrix[(1, i)], matrix[(2, i)]).norm())
+            .fold(0.0, f32::max);
+
+        Self::new(vec4_to_vec3(&center), self.radius * scale)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    // Normalized.
+    pub direction: Vec3
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction: direction.normalize() }
+    }
+
+    // Ray from the near plane through a point in NDC, e.g. for picking under a cell.
+    pub fn through_ndc(inv_vp_matrix: &Mat4, x: f32, y: f32) -> Self {
+        let near = inv_vp_matrix * vec4(x, y, -1.0, 1.0);
+        let far = inv_vp_matrix * vec4(x, y, 1.0, 1.0);
+        let near = vec4_to_vec3(&near) / near.w;
+
+        Self::new(near, vec4_to_vec3(&far) / far.w - near)
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    // Distance to where the ray enters the box (0 if it starts inside), using the slab method.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::INFINITY;
+
+        for i in 0..3 {
+            let inv = 1.0 / self.direction[i];
+            let t0 = (aabb.min[i] - self.origin[i]) * inv;
+            let t1 = (aabb.max[i] - self.origin[i]) * inv;
+
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+
+        if t_min <= t_max { Some(t_min) } else { None }
+    }
+
+    // Möller–Trumbore, hits from both sides.
+    pub fn intersect_triangle(&self, a: &Vec3, b: &Vec3, c: &Vec3) -> Option<f32> {
+        let ab = b - a;
+        let ac = c - a;
+        let p = self.direction.cross(&ac);
+        let det = ab.dot(&p);
+
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(&p) / det;
+
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = to_origin.cross(&ab);
+        let v = self.direction.dot(&q) / det;
+
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = ac.dot(&q) / det;
+        if t >= 0.0 { Some(t) } else { None }
+    }
+}
@@ -0,0 +1,477 @@
+use glm::{make_vec3, Vec3};
+
+use crate::{bounds::{Aabb, Ray}, frustum::Frustum, mesh::Mesh};
+
+// Handle of an object in a `Bvh`, stays valid until the object is removed.
+pub type ProxyId = usize;
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    aabb: Aabb,
+    parent: Option<usize>,
+    children: Option<[usize; 2]>,
+    // Only leaves hold items.
+    item: Option<T>
+}
+
+// Dynamic bounding volume hierarchy over objects, e.g. scene objects for culling, picking and collision.
+// Leaves store boxes enlarged by `margin` so objects moving a little don't require any tree changes,
+// objects leaving their enlarged box are reinserted and the boxes of their ancestors refitted.
+#[derive(Debug, Clone)]
+pub struct Bvh<T> {
+    nodes: Vec<Node<T>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    pub margin: f32,
+    len: usize
+}
+
+impl<T> Default for Bvh<T> {
+    fn default() -> Self {
+        Self::new(0.1)
+    }
+}
+
+impl<T> Bvh<T> {
+    pub fn new(margin: f32) -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+            margin,
+            len: 0
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, id: ProxyId) -> Option<&T> {
+        self.nodes.get(id).and_then(|n| n.item.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: ProxyId) -> Option<&mut T> {
+        self.nodes.get_mut(id).and_then(|n| n.item.as_mut())
+    }
+
+    // The enlarged box stored for an object.
+    pub fn fat_aabb(&self, id: ProxyId) -> Option<Aabb> {
+        self.get(id).map(|_| self.nodes[id].aabb)
+    }
+
+    pub fn insert(&mut self, aabb: Aabb, item: T) -> ProxyId {
+        let margin = Vec3::repeat(self.margin);
+        let leaf = self.allocate(Node {
+            aabb: Aabb::new(aabb.min - margin, aabb.max + margin),
+            parent: None,
+            children: None,
+            item: Some(item)
+        });
+
+        self.insert_leaf(leaf);
+        self.len += 1;
+        leaf
+    }
+
+    pub fn remove(&mut self, id: ProxyId) -> Option<T> {
+        let item = self.nodes.get_mut(id)?.item.take()?;
+
+        self.remove_leaf(id);
+        self.free.push(id);
+        self.len -= 1;
+        Some(item)
+    }
+
+    // Moves an object to its new box. Returns true if it left its enlarged box and was reinserted.
+    pub fn update(&mut self, id: ProxyId, aabb: Aabb) -> bool {
+        if self.get(id).is_none() || self.nodes[id].aabb.contains(&aabb) {
+            return false;
+        }
+
+        let margin = Vec3::repeat(self.margin);
+
+        self.remove_leaf(id);
+        self.nodes[id].aabb = Aabb::new(aabb.min - margin, aabb.max + margin);
+        self.insert_leaf(id);
+        true
+    }
+
+    // Calls `f` for every object whose box overlaps `aabb`.
+    pub fn query_aabb<F: FnMut(ProxyId, &T)>(&self, aabb: &Aabb, f: F) {
+        self.query(|node_aabb| node_aabb.intersects(aabb), f);
+    }
+
+    // Calls `f` for every object whose box is at least partially inside the frustum.
+    pub fn query_frustum<F: FnMut(ProxyId, &T)>(&self, frustum: &Frustum, f: F) {
+        self.query(|node_aabb| frustum.intersects_aabb(node_aabb), f);
+    }
+
+    // Nearest hit along the ray within `max_t`. `hit` does the exact test for an object whose box
+    // the ray passes through and returns the distance along the ray, e.g. a triangle or box test.
+    pub fn ray_cast<F>(&self, ray: &Ray, max_t: f32, mut hit: F) -> Option<(ProxyId, f32)>
+    where
+        F: FnMut(ProxyId, &T, &Ray) -> Option<f32>
+    {
+        let mut nearest: Option<(ProxyId, f32)> = None;
+        let mut stack = self.root.into_iter().collect::<Vec<_>>();
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let limit = nearest.map_or(max_t, |(_, t)| t);
+
+            // Prune subtrees that start further away than the current nearest hit.
+            match ray.intersect_aabb(&node.aabb) {
+                Some(t) if t <= limit => (),
+                _ => continue
+            }
+
+            match (&node.children, &node.item) {
+                (Some(children), _) => stack.extend(children),
+                (None, Some(item)) => {
+                    if let Some(t) = hit(index, item, ray).filter(|t| *t <= limit) {
+                        nearest = Some((index, t));
+                    }
+                },
+                (None, None) => ()
+            }
+        }
+
+        nearest
+    }
+
+    // Every pair of objects whose boxes overlap, e.g. as a broad phase for collision detection.
+    pub fn overlapping_pairs(&self) -> Vec<(ProxyId, ProxyId)> {
+        let mut pairs = Vec::new();
+
+        for (id, node) in self.nodes.iter().enumerate() {
+            if node.item.is_none() {
+                continue;
+            }
+
+            self.query_aabb(&node.aabb, |other, _| {
+                if other > id {
+                    pairs.push((id, other));
+                }
+            });
+        }
+
+        pairs
+    }
+
+    fn query<P: Fn(&Aabb) -> bool, F: FnMut(ProxyId, &T)>(&self, overlaps: P, mut f: F) {
+        let mut stack = self.root.into_iter().collect::<Vec<_>>();
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if !overlaps(&node.aabb) {
+                continue;
+            }
+
+            match (&node.children, &node.item) {
+                (Some(children), _) => stack.extend(children),
+                (None, Some(item)) => f(index, item),
+                (None, None) => ()
+            }
+        }
+    }
+
+    fn allocate(&mut self, node: Node<T>) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            },
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        let Some(root) = self.root else {
+            self.root = Some(leaf);
+            self.nodes[leaf].parent = None;
+            return;
+        };
+
+        // Walk down picking the child whose box grows the least in surface area (greedy SAH).
+        let leaf_aabb = self.nodes[leaf].aabb;
+        let mut sibling = root;
+
+        while let Some([left, right]) = self.nodes[sibling].children {
+            let cost = |child: usize| {
+                let aabb = &self.nodes[child].aabb;
+                aabb.union(&leaf_aabb).surface_area() - aabb.surface_area()
+            };
+
+            let combined = self.nodes[sibling].aabb.union(&leaf_aabb).surface_area();
+
+            // Creating a new parent here is cheaper than descending further.
+            if combined < cost(left).min(cost(right)) + self.nodes[sibling].aabb.surface_area() {
+                break;
+            }
+
+            sibling = if cost(left) <= cost(right) { left } else { right };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let new_parent = self.allocate(Node {
+            aabb: self.nodes[sibling].aabb.union(&leaf_aabb),
+            parent: old_parent,
+            children: Some([sibling, leaf]),
+            item: None
+        });
+
+        self.nodes[sibling].parent = Some(new_parent);
+        self.nodes[leaf].parent = Some(new_parent);
+
+        match old_parent {
+            Some(parent) => self.replace_child(parent, sibling, new_parent),
+            None => self.root = Some(new_parent)
+        }
+
+        self.refit(old_parent);
+    }
+
+    fn remove_leaf(&mut self, leaf: usize) {
+        let Some(parent) = self.nodes[leaf].parent else {
+            self.root = None;
+            return;
+        };
+
+        let Some([left, right]) = self.nodes[parent].children else {
+            return;
+        };
+
+        // The sibling takes the parent's place.
+        let sibling = if left == leaf { right } else { left };
+        let grand_parent = self.nodes[parent].parent;
+
+        self.nodes[sibling].parent = grand_parent;
+        self.nodes[leaf].parent = None;
+        self.nodes[parent].children = None;
+        self.free.push(parent);
+
+        match grand_parent {
+            Some(grand_parent) => {
+                self.replace_child(grand_parent, parent, sibling);
+                self.refit(Some(grand_parent));
+            },
+            None => self.root = Some(sibling)
+        }
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if let Some(children) = &mut self.nodes[parent].children {
+            for child in children.iter_mut().filter(|c| **c == old) {
+                *child = new;
+            }
+        }
+    }
+
+    // Recomputes boxes from `node` up to the root.
+    fn refit(&mut self, mut node: Option<usize>) {
+        while let Some(index) = node {
+            if let Some([left, right]) = self.nodes[index].children {
+                self.nodes[index].aabb = self.nodes[left].aabb.union(&self.nodes[right].aabb);
+            }
+
+            node = self.nodes[index].parent;
+        }
+    }
+}
+
+impl Bvh<usize> {
+    // Hierarchy over the triangles of a mesh with the triangle index as item, for precise ray picking.
+    pub fn from_triangles(mesh: &Mesh) -> Self {
+        let mut bvh = Self::new(0.0);
+
+        for (i, indices) in mesh.indices.chunks(3).enumerate() {
+            let positions = indices.iter()
+                .flat_map(|i| mesh.positions[3 * *i as usize..3 * *i as usize + 3].iter().copied())
+                .collect::<Vec<f32>>();
+
+            bvh.insert(Aabb::from_positions(&positions), i);
+        }
+
+        bvh
+    }
+
+    // Nearest triangle of `mesh` hit by a ray in model space, `self` must be built from the same mesh.
+    pub fn ray_cast_triangles(&self, mesh: &Mesh, ray: &Ray, max_t: f32) -> Option<(usize, f32)> {
+        let position = |i: u32| make_vec3(&mesh.positions[3 * i as usize..3 * i as usize + 3]);
+
+        self.ray_cast(ray, max_t, |_, triangle, ray| {
+            let indices = &mesh.indices[3 * triangle..3 * triangle + 3];
+            ray.intersect_triangle(&position(indices[0]), &position(indices[1]), &position(indices[2]))
+        }).map(|(id, t)| (self.nodes[id].item.unwrap_or(id), t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glm::vec3;
+
+    use super::*;
+
+    // Small deterministic generator so the tests don't need a random number crate.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f32 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 40) as f32 / (1u64 << 24) as f32
+        }
+
+        fn aabb(&mut self) -> Aabb {
+            let min = vec3(self.next(), self.next(), self.next()) * 20.0 - Vec3::repeat(10.0);
+            let size = vec3(self.next(), self.next(), self.next()) * 2.0;
+            Aabb::new(min, min + size)
+        }
+    }
+
+    // Every child lies inside its parent and points back at it, and the leaves are the live objects.
+    fn check_tree<T>(bvh: &Bvh<T>) {
+        let mut leaves = 0;
+        let mut stack = bvh.root.into_iter().collect::<Vec<_>>();
+
+        while let Some(index) = stack.pop() {
+            let node = &bvh.nodes[index];
+
+            match node.children {
+                Some(children) => for child in children {
+                    assert_eq!(bvh.nodes[child].parent, Some(index));
+                    assert!(node.aabb.contains(&bvh.nodes[child].aabb));
+                    stack.push(child);
+                },
+                None => {
+                    assert!(node.item.is_some());
+                    leaves += 1;
+                }
+            }
+        }
+
+        assert_eq!(leaves, bvh.len());
+    }
+
+    // Query results against testing every live object's enlarged box directly.
+    fn check_queries(bvh: &Bvh<usize>, boxes: &[Option<(ProxyId, Aabb)>], rng: &mut Lcg) {
+        for _ in 0..20 {
+            let query = rng.aabb();
+
+            let mut found = Vec::new();
+            bvh.query_aabb(&query, |id, item| found.push((id, *item)));
+            found.sort();
+
+            let mut expected = boxes.iter().enumerate()
+                .filter_map(|(item, entry)| entry.map(|(id, _)| (id, item)))
+                .filter(|(id, _)| bvh.fat_aabb(*id).unwrap().intersects(&query))
+                .collect::<Vec<_>>();
+            expected.sort();
+
+            assert_eq!(found, expected);
+
+            // The enlarged boxes never make a query miss an object.
+            for (item, (id, aabb)) in boxes.iter().enumerate().filter_map(|(i, e)| e.map(|e| (i, e))) {
+                if aabb.intersects(&query) {
+                    assert!(found.contains(&(id, item)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn insert_update_remove_match_brute_force() {
+        let mut rng = Lcg(7);
+        let mut bvh = Bvh::new(0.2);
+        let mut boxes = Vec::new();
+
+        for item in 0..200 {
+            let aabb = rng.aabb();
+            boxes.push(Some((bvh.insert(aabb, item), aabb)));
+        }
+
+        check_tree(&bvh);
+        check_queries(&bvh, &boxes, &mut rng);
+
+        // Small moves stay inside the enlarged boxes, large ones reinsert and refit.
+        for (item, entry) in boxes.iter_mut().enumerate() {
+            let (id, aabb) = entry.unwrap();
+            let offset = if item % 2 == 0 { Vec3::repeat(0.1) } else { Vec3::repeat(1.0 + rng.next() * 3.0) };
+            let moved = Aabb::new(aabb.min + offset, aabb.max + offset);
+
+            assert_eq!(bvh.update(id, moved), item % 2 != 0);
+            assert!(bvh.fat_aabb(id).unwrap().contains(&moved));
+            *entry = Some((id, moved));
+        }
+
+        check_tree(&bvh);
+        check_queries(&bvh, &boxes, &mut rng);
+
+        for item in (0..boxes.len()).step_by(3) {
+            let (id, _) = boxes[item].take().unwrap();
+
+            assert_eq!(bvh.remove(id), Some(item));
+            assert_eq!(bvh.remove(id), None);
+        }
+
+        check_tree(&bvh);
+        check_queries(&bvh, &boxes, &mut rng);
+
+        // Freed nodes are reused for new objects.
+        let node_count = bvh.nodes.len();
+
+        for item in 0..10 {
+            let aabb = rng.aabb();
+            boxes.push(Some((bvh.insert(aabb, boxes.len() + item), aabb)));
+        }
+
+        assert_eq!(bvh.nodes.len(), node_count);
+        check_tree(&bvh);
+    }
+
+    #[test]
+    fn remove_everything() {
+        let mut rng = Lcg(3);
+        let mut bvh = Bvh::default();
+        let ids = (0..5).map(|item| bvh.insert(rng.aabb(), item)).collect::<Vec<_>>();
+
+        for id in ids {
+            bvh.remove(id);
+            check_tree(&bvh);
+        }
+
+        assert!(bvh.is_empty());
+        assert!(bvh.root.is_none());
+    }
+
+    #[test]
+    fn overlapping_pairs_match_brute_force() {
+        let mut rng = Lcg(11);
+        let mut bvh = Bvh::new(0.0);
+        let ids = (0..60).map(|item| bvh.insert(rng.aabb(), item)).collect::<Vec<_>>();
+
+        let mut expected = Vec::new();
+
+        for (i, a) in ids.iter().enumerate() {
+            for b in &ids[i + 1..] {
+                if bvh.fat_aabb(*a).unwrap().intersects(&bvh.fat_aabb(*b).unwrap()) {
+                    expected.push((*a.min(b), *a.max(b)));
+                }
+            }
+        }
+
+        let mut pairs = bvh.overlapping_pairs();
+        pairs.sort();
+        expected.sort();
+
+        assert_eq!(pairs, expected);
+    }
+}
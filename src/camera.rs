@@ -0,0 +1,118 @@
+use std::f32::consts::PI;
+
+use glm::{angle, look_at, perspective, rotate_vec3, vec3, Mat4, Vec3};
+
+use crate::{input::Key, raster::Framebuf};
+
+const ORBIT_STEP: f32 = 0.1;
+const DOLLY_STEP: f32 = 1.1;
+const POLE_MARGIN: f32 = 0.05;
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    // Vertical field of view in radians.
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+    // Width over height of one terminal cell, about 1:2 in most fonts.
+    pub cell_aspect: f32
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: vec3(0.0, 0.0, 4.0),
+            target: vec3(0.0, 0.0, 0.0),
+            // Framebuffer rows grow downwards, so flipping up keeps models upright.
+            up: vec3(0.0, -1.0, 0.0),
+            fov: 60f32.to_radians(),
+            near: 0.1,
+            far: 1000.0,
+            cell_aspect: 0.5
+        }
+    }
+}
+
+impl Camera {
+    // Looks at a bounding sphere from `direction` so that it fills the view.
+    pub fn framing(center: &Vec3, radius: f32, direction: &Vec3) -> Self {
+        let defaults = Self::default();
+        let distance = radius / (defaults.fov / 2.0).sin();
+
+        Self {
+            position: center + direction.normalize() * distance,
+            target: *center,
+            near: radius * 0.1,
+            far: distance + radius * 10.0,
+            ..defaults
+        }
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        look_at(&self.position, &self.target, &self.up)
+    }
+
+    pub fn proj_matrix(&self, aspect: f32) -> Mat4 {
+        perspective(aspect, self.fov, self.near, self.far)
+    }
+
+    // Projection filling the viewport of `fb`, corrected for the shape its pixels have inside terminal cells.
+    pub fn fb_proj_matrix(&self, fb: &Framebuf) -> Mat4 {
+        let (cell_w, cell_h) = fb.cell_mode.cell_size();
+        let pixel_aspect = self.cell_aspect * cell_h as f32 / cell_w as f32;
+        let viewport = fb.viewport_rect();
+
+        self.proj_matrix(viewport.w.max(1) as f32 / viewport.h.max(1) as f32 * pixel_aspect)
+    }
+
+    // Sets the cell aspect from a terminal's size in pixels and cells, ignoring sizes clients don't know.
+    pub fn set_cell_aspect_from_pixels(&mut self, pix_width: u32, pix_height: u32, cols: u32, rows: u32) {
+        if pix_width > 0 && pix_height > 0 && cols > 0 && rows > 0 {
+            self.cell_aspect = (pix_width as f32 / cols as f32) / (pix_height as f32 / rows as f32);
+        }
+    }
+
+    // Moves the camera around its target, `yaw` around the up axis and `pitch` towards it, in radians.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let up = self.up.normalize();
+        let offset = rotate_vec3(&(self.position - self.target), yaw, &up);
+        let right = offset.cross(&up);
+
+        // Stops short of the poles, where the view direction would be parallel to up.
+        let angle = angle(&offset, &up);
+        let pitch = pitch.clamp(angle - (PI - POLE_MARGIN), angle - POLE_MARGIN);
+
+        self.position = match right.try_normalize(f32::EPSILON) {
+            Some(right) => self.target + rotate_vec3(&offset, pitch, &right),
+            None => self.target + offset
+        };
+    }
+
+    // Scales the distance to the target, below 1 moves closer.
+    pub fn dolly(&mut self, factor: f32) {
+        let offset = (self.position - self.target) * factor;
+
+        if offset.norm() > self.near * 2.0 {
+            self.position = self.target + offset;
+        }
+    }
+
+    // Arrow keys orbit and +/- zoom, cell size reports fix the aspect. Returns whether the view changed.
+    pub fn handle_key(&mut self, key: &Key) -> bool {
+        match key {
+            Key::Left => self.orbit(-ORBIT_STEP, 0.0),
+            Key::Right => self.orbit(ORBIT_STEP, 0.0),
+            Key::Up => self.orbit(0.0, ORBIT_STEP),
+            Key::Down => self.orbit(0.0, -ORBIT_STEP),
+            Key::Char('+' | '=') => self.dolly(1.0 / DOLLY_STEP),
+            Key::Char('-') => self.dolly(DOLLY_STEP),
+            Key::CellSize { width, height } => self.set_cell_aspect_from_pixels(*width as u32, *height as u32, 1, 1),
+            _ => return false
+        }
+
+        true
+    }
+}
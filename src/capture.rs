@@ -0,0 +1,65 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::{char::CharColor, error::EngineError, raster::Framebuf, texture::Texture};
+
+pub struct GifOptions {
+    pub frames: usize,
+    // Time per frame in milliseconds, GIFs store it in steps of 10 ms. Viewers play shorter
+    // delays at about 100 ms, so at least 10 ms are required.
+    pub frame_time: u32,
+    // Each framebuffer pixel becomes `scale` x `scale` image pixels.
+    pub scale: usize,
+    pub background: CharColor
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        Self {
+            frames: 60,
+            frame_time: 50,
+            scale: 4,
+            background: CharColor { r: 0, g: 0, b: 0 }
+        }
+    }
+}
+
+// Renders `options.frames` frames offline and writes the resolved pixel buffer as a looping GIF.
+// `draw` gets the frame index and time in milliseconds and must leave `fb` resolved.
+pub fn render_gif<P, F>(path: P, fb: &mut Framebuf, options: &GifOptions, mut draw: F) -> Result<(), EngineError>
+where
+    P: AsRef<Path>,
+    F: FnMut(&mut Framebuf, usize, f32)
+{
+    let delay = u16::try_from(options.frame_time / 10).ok().filter(|d| *d > 0)
+        .ok_or_else(|| EngineError::Invalid(format!("Invalid frame time {} ms, GIFs need 10 to 655350 ms", options.frame_time)))?;
+
+    let scale = options.scale.max(1);
+    let (w, h) = (fb.w * scale, fb.h * scale);
+    let (Ok(gif_w), Ok(gif_h)) = (u16::try_from(w), u16::try_from(h)) else {
+        return Err(EngineError::Invalid(format!("GIF of {w}x{h} pixels is too large, at most 65535 per side")));
+    };
+
+    let mut encoder = Encoder::new(BufWriter::new(File::create(path)?), gif_w, gif_h, &[])?;
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    for i in 0..options.frames {
+        draw(fb, i, (i as u32 * options.frame_time) as f32);
+
+        let image = upscale(&fb.pixel_image(options.background), scale);
+        let mut frame = Frame::from_rgb_speed(gif_w, gif_h, &image.to_rgb_bytes(), 10);
+        frame.delay = delay;
+
+        encoder.write_frame(&frame)?;
+    }
+
+    Ok(())
+}
+
+fn upscale(image: &Texture, scale: usize) -> Texture {
+    let (w, h) = (image.w * scale, image.h * scale);
+    let pixels = (0..w * h).map(|i| image.get(i % w / scale, i / w / scale)).collect();
+
+    Texture::new(w, h, pixels)
+}
@@ -3,6 +3,8 @@ pub static ANSI_CLEAR_SCREEN: &str = "\x1b[2J";
 pub static ANSI_HIDE_CURSOR: &str = "\x1b[?25l";
 pub static ANSI_SHOW_CURSOR: &str = "\x1b[?25h";
 pub static ANSI_MOVE_CURSOR_TO_START: &str = "\x1b[H";
+// XTWINOPS request for the size of a cell in pixels, answered with ESC [ 6 ; height ; width t.
+pub static ANSI_QUERY_CELL_SIZE: &str = "\x1b[16t";
 
 #[derive(Debug, Clone, Copy)]
 pub enum CharColorLayer {
@@ -11,6 +13,8 @@ pub enum CharColorLayer {
 }
 
 pub enum AnsiColorMode {
+    // Plain characters without escape sequences, e.g. for ASCII art in files.
+    NoColor,
     Ansi256,
     AnsiTrueColor
 }
@@ -29,13 +33,14 @@ impl CharColor {
             CharColorLayer::Background => "48",
         };
 
-        return match mode {
+        match mode {
+            AnsiColorMode::NoColor => String::new(),
             AnsiColorMode::Ansi256 => format!("\x1b[{layer_str};5;{}m", self.to_ansi256()),
             AnsiColorMode::AnsiTrueColor => format!("\x1b[{layer_str};2;{};{};{}m", self.r, self.g, self.b)
         }
     }
 
-    fn to_ansi256(&self) -> u8 {
+    fn to_ansi256(self) -> u8 {
         if self.r == self.g && self.g == self.b {
             if self.r < 8 {
                 return 16
@@ -48,30 +53,130 @@ impl CharColor {
             return (((self.r - 8) as f32 / 247.0) * 24.0).round() as u8 + 232
         }
         
-        return (16.0 + 
+        (16.0 + 
             36.0 * (self.r as f32 / 255.0 * 5.0).round() + 
             6.0 * (self.g as f32 / 255.0 * 5.0).round() + 
             (self.b as f32 / 255.0 * 5.0).round()) as u8
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy)]
 pub struct CharInfo {
     pub char_code: char,
     pub fg_color: Option<CharColor>,
     pub bg_color: Option<CharColor>
 }
 
-impl CharInfo {
-    pub fn default() -> Self {
-        return Self {
+impl Default for CharInfo {
+    fn default() -> Self {
+        Self {
             char_code: ' ',
             fg_color: None,
             bg_color: None
-        };
+        }
+    }
+}
+
+// Bit of each Braille dot, indexed row by row (2 columns, 4 rows).
+const BRAILLE_DOTS: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+
+// Characters from sparse to dense, used to show brightness in ASCII mode.
+pub const ASCII_RAMP: [char; 10] = [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+impl CharInfo {
+    // Encodes two vertically stacked pixels using upper and lower half blocks.
+    pub fn half_block(top: Option<CharColor>, bottom: Option<CharColor>) -> Self {
+        match (top, bottom) {
+            (Some(top), bottom) => Self { char_code: '▀', fg_color: Some(top), bg_color: bottom },
+            (None, Some(bottom)) => Self { char_code: '▄', fg_color: Some(bottom), bg_color: None },
+            (None, None) => Self::default()
+        }
+    }
+
+    // Encodes 2x4 pixels (row by row) as Braille dots. Braille cells have a single color 
+    // so the dots take the average color of the covered pixels.
+    pub fn braille(pixels: &[Option<CharColor>; 8]) -> Self {
+        let mut bits = 0;
+        let mut sum = [0u32; 3];
+        let mut count = 0;
+
+        for (pixel, bit) in pixels.iter().zip(BRAILLE_DOTS) {
+            if let Some(color) = pixel {
+                bits |= bit;
+                sum[0] += color.r as u32;
+                sum[1] += color.g as u32;
+                sum[2] += color.b as u32;
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            return Self::default();
+        }
+
+        Self {
+            char_code: char::from_u32(0x2800 + bits).unwrap_or(' '),
+            fg_color: Some(CharColor { r: (sum[0] / count) as u8, g: (sum[1] / count) as u8, b: (sum[2] / count) as u8 }),
+            bg_color: None
+        }
+    }
+
+    // Encodes two vertically stacked pixels as one ASCII character picked by brightness.
+    pub fn ascii(top: Option<CharColor>, bottom: Option<CharColor>) -> Self {
+        let covered = [top, bottom].into_iter().flatten().collect::<Vec<_>>();
+
+        if covered.is_empty() {
+            return Self::default();
+        }
+
+        let sum = covered.iter().fold([0u32; 3], |sum, c| [sum[0] + c.r as u32, sum[1] + c.g as u32, sum[2] + c.b as u32]);
+        // Uncovered pixels count as black so half covered cells get lighter characters.
+        let color = CharColor { r: (sum[0] / 2) as u8, g: (sum[1] / 2) as u8, b: (sum[2] / 2) as u8 };
+        let luma = (0.2126 * color.r as f32 + 0.7152 * color.g as f32 + 0.0722 * color.b as f32) / 255.0;
+        let index = 1 + (luma * (ASCII_RAMP.len() - 2) as f32).round() as usize;
+
+        let count = covered.len() as u32;
+        Self {
+            char_code: ASCII_RAMP[index.min(ASCII_RAMP.len() - 1)],
+            fg_color: Some(CharColor { r: (sum[0] / count) as u8, g: (sum[1] / count) as u8, b: (sum[2] / count) as u8 }),
+            bg_color: None
+        }
+    }
+
+    // Whether the glyph covers a point of the cell, `u` and `v` in [0, 1) from the top left corner.
+    // Approximates how a terminal draws the characters the engine emits.
+    pub fn covers(&self, u: f32, v: f32) -> bool {
+        match self.char_code {
+            '▀' => v < 0.5,
+            '▄' => v >= 0.5,
+            '█' => true,
+            c @ '\u{2800}'..='\u{28ff}' => {
+                let (col, row) = ((u * 2.0) as usize, (v * 4.0) as usize);
+                let (du, dv) = (u * 2.0 % 1.0 - 0.5, v * 4.0 % 1.0 - 0.5);
+
+                (c as u32 - 0x2800) & BRAILLE_DOTS[row.min(3) * 2 + col.min(1)] != 0 && du * du + dv * dv < 0.16
+            },
+            c => match ASCII_RAMP.iter().position(|r| *r == c) {
+                // Approximate ASCII glyphs as centered squares growing with their density.
+                Some(index) => {
+                    let half = (index as f32 / (ASCII_RAMP.len() - 1) as f32).sqrt() / 2.0;
+                    (u - 0.5).abs() < half && (v - 0.5).abs() < half
+                },
+                None => false
+            }
+        }
     }
 
     pub fn to_ansi(&self, mode: &AnsiColorMode) -> String {
+        if let AnsiColorMode::NoColor = mode {
+            // Without colors the background can't be shown, so a half block with both halves covered becomes a full block.
+            return match (self.fg_color, self.bg_color, self.char_code) {
+                (None, None, _) => ' ',
+                (_, Some(_), '▀' | '▄') => '█',
+                (_, _, char_code) => char_code
+            }.to_string();
+        }
+
         let mut str = String::with_capacity(45);
         str.push_str("\x1b[0m");
 
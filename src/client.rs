@@ -1,20 +1,65 @@
 use russh::server::Handle;
 use uuid::Uuid;
 
-use crate::raster::Framebuf;
+use crate::{camera::Camera, input::KeyReader, raster::{Framebuf, ResolveFilter}, record::CastRecorder};
+
+const MAX_COLS: u32 = 512;
+const MAX_ROWS: u32 = 256;
 
 pub struct Client {
     pub handle: Handle,
     pub uuid: Uuid,
-    pub fb: Framebuf
+    pub fb: Framebuf,
+    // The client's own view of the shared world.
+    pub camera: Camera,
+    // Columns and rows of the client's terminal.
+    pub term_size: (u32, u32),
+    // Escape sequences can be split across SSH packets.
+    pub keys: KeyReader,
+    pub recorder: Option<CastRecorder>
 }
 
 impl Client {
-    pub fn new(handle: Handle, uuid: Uuid) -> Self {
-        Self {
+    pub fn new(handle: Handle, uuid: Uuid, camera: Camera) -> Self {
+        let mut client = Self {
             handle,
             uuid,
-            fb: Framebuf::new(96, 96)
+            fb: Framebuf::new(0, 0),
+            camera,
+            term_size: (80, 24),
+            keys: KeyReader::default(),
+            recorder: None
+        };
+
+        client.fb.set_samples(2);
+        client.resize(80, 24);
+        client
+    }
+
+    // Fits the framebuffer to a terminal of `cols` x `rows` cells, keeping the last row for the status line.
+    // Clients that don't know their size report 0, those keep the current size. Sizes are clamped
+    // so a client can't make the server allocate arbitrarily large buffers.
+    pub fn resize(&mut self, cols: u32, rows: u32) {
+        if cols == 0 || rows == 0 {
+            return;
         }
+
+        self.term_size = (cols.min(MAX_COLS), rows.min(MAX_ROWS));
+        fit_to_terminal(&mut self.fb, self.term_size);
+    }
+
+    // Supersampling factor per axis and the filter used to resolve it, trading CPU time for smoother edges.
+    pub fn set_quality(&mut self, samples: usize, filter: ResolveFilter) {
+        self.fb.set_samples(samples);
+        self.fb.filter = filter;
+    }
+}
+
+// Resizes `fb` to the cells of a terminal of `term_size` left above the status line, if it doesn't fit already.
+pub fn fit_to_terminal(fb: &mut Framebuf, (cols, rows): (u32, u32)) {
+    let (cols, rows) = (cols as usize, rows.saturating_sub(1) as usize);
+
+    if (fb.cols(), fb.rows()) != (cols, rows) {
+        fb.resize_cells(cols, rows);
     }
-}
\ No newline at end of file
+}
@@ -25,7 +25,7 @@ impl ClipComponent {
 
 pub fn clip_triangle(vertices: &[&Vertex; 3]) -> Vec<Vertex> {
     let result = clip_component(
-        &vec![vertices[0].clone(), vertices[1].clone(), vertices[2].clone()], 
+        &[vertices[0].clone(), vertices[1].clone(), vertices[2].clone()], 
         &ClipComponent::X);
 
     if result.is_empty() {
@@ -38,20 +38,20 @@ pub fn clip_triangle(vertices: &[&Vertex; 3]) -> Vec<Vertex> {
         return result;
     }
 
-    return clip_component(&result, &ClipComponent::Z);
+    clip_component(&result, &ClipComponent::Z)
 }
 
-fn clip_component(vertices: &Vec<Vertex>, component: &ClipComponent) -> Vec<Vertex> {
-    let result = clip_component_signed(&vertices, component, 1.0);
+fn clip_component(vertices: &[Vertex], component: &ClipComponent) -> Vec<Vertex> {
+    let result = clip_component_signed(vertices, component, 1.0);
     
     if result.is_empty() {
         return result;
     }
 
-    return clip_component_signed(&result, component, -1.0);
+    clip_component_signed(&result, component, -1.0)
 }
 
-fn clip_component_signed(vertices: &Vec<Vertex>, component: &ClipComponent, sign: f32) -> Vec<Vertex> {
+fn clip_component_signed(vertices: &[Vertex], component: &ClipComponent, sign: f32) -> Vec<Vertex> {
     let mut result: Vec<Vertex> = Vec::with_capacity(vertices.len());
 
     // Iterate all lines and clip if necessary.
@@ -81,7 +81,7 @@ pub fn should_backface_cull(vertices: &[Vec4; 3], model_matrix: &Mat4, camera_po
     // Put points in model space
     let points = vertices.iter().map(|p| vec4_to_vec3(&(model_matrix * p))).collect::<Vec<_>>();
     // Pick any one of the triangle's points and calculate direction from camera
-    let direction_towards_point = (&points[0] - camera_pos).normalize();
+    let direction_towards_point = (points[0] - camera_pos).normalize();
     // Calculate normal from winding order
     let normal = (points[1] - points[0]).cross(&(points[2] - points[0])).normalize();
     
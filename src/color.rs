@@ -0,0 +1,158 @@
+use std::ops::{Add, AddAssign, Mul, MulAssign, Sub};
+
+use crate::{char::CharColor, vertex::lerp};
+
+// Maps unbounded linear values into [0, 1] before encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMap {
+    // Clamps, highlights above 1 are lost.
+    Clamp,
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve.
+    Aces
+}
+
+impl ToneMap {
+    pub fn apply(self, v: f32) -> f32 {
+        let v = v.max(0.0);
+
+        match self {
+            ToneMap::Clamp => v.min(1.0),
+            ToneMap::Reinhard => v / (1.0 + v),
+            ToneMap::Aces => ((v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14)).clamp(0.0, 1.0)
+        }
+    }
+}
+
+// Color in linear light, where adding and multiplying behaves physically.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinearColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32
+}
+
+impl LinearColor {
+    pub const BLACK: Self = Self::new(0.0, 0.0, 0.0);
+    pub const WHITE: Self = Self::new(1.0, 1.0, 1.0);
+
+    pub const fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    pub const fn gray(v: f32) -> Self {
+        Self::new(v, v, v)
+    }
+
+    pub fn lerp(&self, other: &Self, amount: f32) -> Self {
+        Self::new(lerp(self.r, other.r, amount), lerp(self.g, other.g, amount), lerp(self.b, other.b, amount))
+    }
+
+    pub fn clamp(&self, min: f32, max: f32) -> Self {
+        Self::new(self.r.clamp(min, max), self.g.clamp(min, max), self.b.clamp(min, max))
+    }
+
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    // Decodes an sRGB color.
+    pub fn from_srgb(color: &CharColor) -> Self {
+        Self::new(srgb_to_linear(color.r), srgb_to_linear(color.g), srgb_to_linear(color.b))
+    }
+
+    // Tone maps and encodes to sRGB.
+    pub fn to_srgb(self, tone_map: ToneMap) -> CharColor {
+        CharColor {
+            r: linear_to_srgb(tone_map.apply(self.r)),
+            g: linear_to_srgb(tone_map.apply(self.g)),
+            b: linear_to_srgb(tone_map.apply(self.b))
+        }
+    }
+}
+
+pub fn srgb_to_linear(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Expects `v` in [0, 1].
+pub fn linear_to_srgb(v: f32) -> u8 {
+    let v = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+impl From<CharColor> for LinearColor {
+    fn from(color: CharColor) -> Self {
+        Self::from_srgb(&color)
+    }
+}
+
+impl From<LinearColor> for CharColor {
+    fn from(color: LinearColor) -> Self {
+        color.to_srgb(ToneMap::Clamp)
+    }
+}
+
+impl Add for LinearColor {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+    }
+}
+
+impl AddAssign for LinearColor {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for LinearColor {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.r - rhs.r, self.g - rhs.g, self.b - rhs.b)
+    }
+}
+
+// Component-wise, e.g. light color times surface albedo.
+impl Mul for LinearColor {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b)
+    }
+}
+
+impl Mul<f32> for LinearColor {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl Mul<LinearColor> for f32 {
+    type Output = LinearColor;
+
+    fn mul(self, rhs: LinearColor) -> LinearColor {
+        rhs * self
+    }
+}
+
+impl MulAssign<f32> for LinearColor {
+    fn mul_assign(&mut self, rhs: f32) {
+        *self = *self * rhs;
+    }
+}
@@ -0,0 +1,103 @@
+use glm::{make_vec2, make_vec3, rotate, scale, translation, vec3, vec4_to_vec3, Vec3};
+
+use crate::{camera::Camera, color::{LinearColor, ToneMap}, error::EngineError, mesh::{Mesh, NormalMode}, raster::{normal_matrix, Framebuf, Instance}, vertex::Vertex};
+
+// The rotating skull scene, shared by the SSH server and the offline renderers.
+pub struct Demo {
+    pub mesh: Mesh,
+    // Marker drawn where other players' cameras are.
+    pub avatar: Mesh,
+    // Where viewers start, every SSH client gets its own copy to move around.
+    pub camera: Camera
+}
+
+impl Demo {
+    pub fn load() -> Result<Self, EngineError> {
+        let path = "assets/low-poly-skull.obj";
+
+        Ok(Self {
+            mesh: Mesh::load_obj_first(path).map_err(|e| EngineError::asset(path, e))?,
+            avatar: avatar_mesh(),
+            camera: Camera {
+                position: vec3(0.0, -0.5, 4.0),
+                target: vec3(0.0, -0.5, 0.0),
+                fov: 50.7f32.to_radians(),
+                near: 0.0001,
+                ..Default::default()
+            }
+        })
+    }
+
+    // Clears and draws the scene as it looks `elapsed_time` milliseconds after start.
+    pub fn draw(&self, fb: &mut Framebuf, elapsed_time: f32) {
+        self.draw_view(fb, &self.camera, &[], elapsed_time);
+    }
+
+    // Same as `draw` but seen through `camera`, with an avatar at each of the `players` positions.
+    pub fn draw_view(&self, fb: &mut Framebuf, camera: &Camera, players: &[Vec3], elapsed_time: f32) {
+        fb.clear();
+        fb.post.set_camera(camera);
+
+        let proj_matrix = camera.fb_proj_matrix(fb);
+        let view_matrix = camera.view_matrix();
+
+        let mut model_matrix = rotate(&glm::identity(), elapsed_time / 600.0, &vec3(0.0, 1.0, 0.0));
+        //model_matrix = rotate(&model_matrix, elapsed_time / 1500.0, &vec3(1.0, 0.0, 0.0));
+        model_matrix = scale(&model_matrix, &vec3(1.8, 1.8, 1.8));
+
+        let vp_matrix = proj_matrix * view_matrix;
+        let normal_matrix = normal_matrix(&model_matrix);
+
+        fb.draw_model(&self.mesh, &model_matrix, &vp_matrix, &normal_matrix, &camera.position, &|vertex: &Vertex| {
+            let light_color = LinearColor::WHITE;
+            let light_pos = vec3(100.0, 0.0, 50.0);
+            let light_direction = (light_pos - vec4_to_vec3(&vertex.position)).normalize();
+
+            let ambient_strength = 0.03;
+            let ambient = ambient_strength * light_color;
+
+            let normal = make_vec3(&vertex.attributes[2..5]);
+            let diffuse = normal.dot(&light_direction).max(0.0) * light_color;
+
+            let tex_coord = make_vec2(&vertex.attributes[0..2]);
+            let size = 8.0;
+            let pattern: bool = ((tex_coord[0] * size % 1.0) > 0.5) ^ ((tex_coord[1] * size % 1.0) < 0.5);
+            let object_color = if pattern { LinearColor::new(0.6, 0.69, 1.0) } else { LinearColor::new(0.03, 0.32, 0.6) };
+            let result = (ambient + diffuse) * object_color;
+
+            result.to_srgb(ToneMap::Aces)
+        });
+
+        let avatars = players.iter()
+            .map(|position| Instance { tint: AVATAR_COLOR, ..Instance::new(translation(position)) })
+            .collect::<Vec<_>>();
+
+        fb.draw_instanced(&self.avatar, &avatars, &vp_matrix, &camera.position, &|vertex: &Vertex| {
+            let normal = make_vec3(&vertex.attributes[2..5]);
+            let diffuse = normal.dot(&vec3(0.5, -0.7, 0.5).normalize()).max(0.0);
+            let color = LinearColor::new(vertex.attributes[5], vertex.attributes[6], vertex.attributes[7]);
+
+            (color * (0.2 + diffuse)).to_srgb(ToneMap::Aces)
+        });
+
+        fb.resolve();
+    }
+}
+
+const AVATAR_COLOR: LinearColor = LinearColor::new(1.0, 0.35, 0.05);
+
+// Small octahedron with flat normals.
+fn avatar_mesh() -> Mesh {
+    let size = 0.25;
+    let positions = vec![
+        size, 0.0, 0.0, -size, 0.0, 0.0,
+        0.0, size, 0.0, 0.0, -size, 0.0,
+        0.0, 0.0, size, 0.0, 0.0, -size
+    ];
+    let indices = vec![0, 2, 4, 2, 1, 4, 1, 3, 4, 3, 0, 4, 2, 0, 5, 1, 2, 5, 3, 1, 5, 0, 3, 5];
+
+    let mut mesh = Mesh::new("avatar".to_owned(), positions, Vec::new(), Vec::new(), indices);
+    mesh.generate_missing(NormalMode::Flat);
+    mesh.update_bounds();
+    mesh
+}
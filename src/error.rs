@@ -0,0 +1,126 @@
+use std::{error::Error, fmt, io, path::Path};
+
+use crate::mesh::MeshError;
+
+// Anything that can go wrong loading assets, setting up rendering or serving clients.
+#[derive(Debug)]
+pub enum EngineError {
+    Io(io::Error),
+    Mesh(MeshError),
+    Gltf(gltf::Error),
+    PngDecoding(png::DecodingError),
+    PngEncoding(png::EncodingError),
+    Gif(gif::EncodingError),
+    Ssh(russh::Error),
+    // The server's private key couldn't be read.
+    Key(russh_keys::Error),
+    // Malformed command line arguments or asset contents.
+    Invalid(String),
+    // Wraps an error with the file it came from.
+    Asset { path: String, source: Box<EngineError> }
+}
+
+impl EngineError {
+    pub fn asset<E: Into<EngineError>>(path: impl AsRef<Path>, source: E) -> Self {
+        Self::Asset { path: path.as_ref().display().to_string(), source: Box::new(source.into()) }
+    }
+}
+
+// For parsers that report malformed files as `io::Error`s.
+pub fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Mesh(e) => write!(f, "{e}"),
+            Self::Gltf(e) => write!(f, "Invalid glTF: {e}"),
+            Self::PngDecoding(e) => write!(f, "Failed to read PNG: {e}"),
+            Self::PngEncoding(e) => write!(f, "Failed to write PNG: {e}"),
+            Self::Gif(e) => write!(f, "Failed to write GIF: {e}"),
+            Self::Ssh(e) => write!(f, "SSH error: {e}"),
+            Self::Key(e) => write!(f, "{e}"),
+            Self::Invalid(message) => write!(f, "{message}"),
+            Self::Asset { path, source } => write!(f, "Failed to load {path}: {source}")
+        }
+    }
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Mesh(e) => Some(e),
+            Self::Gltf(e) => Some(e),
+            Self::PngDecoding(e) => Some(e),
+            Self::PngEncoding(e) => Some(e),
+            Self::Gif(e) => Some(e),
+            Self::Ssh(e) => Some(e),
+            Self::Key(e) => Some(e),
+            Self::Invalid(_) => None,
+            Self::Asset { source, .. } => Some(source.as_ref())
+        }
+    }
+}
+
+impl From<io::Error> for EngineError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<MeshError> for EngineError {
+    fn from(e: MeshError) -> Self {
+        Self::Mesh(e)
+    }
+}
+
+impl From<tobj::LoadError> for EngineError {
+    fn from(e: tobj::LoadError) -> Self {
+        Self::Mesh(e.into())
+    }
+}
+
+impl From<gltf::Error> for EngineError {
+    fn from(e: gltf::Error) -> Self {
+        Self::Gltf(e)
+    }
+}
+
+impl From<png::DecodingError> for EngineError {
+    fn from(e: png::DecodingError) -> Self {
+        Self::PngDecoding(e)
+    }
+}
+
+impl From<png::EncodingError> for EngineError {
+    fn from(e: png::EncodingError) -> Self {
+        Self::PngEncoding(e)
+    }
+}
+
+impl From<gif::EncodingError> for EngineError {
+    fn from(e: gif::EncodingError) -> Self {
+        Self::Gif(e)
+    }
+}
+
+impl From<russh::Error> for EngineError {
+    fn from(e: russh::Error) -> Self {
+        Self::Ssh(e)
+    }
+}
+
+impl From<russh_keys::Error> for EngineError {
+    fn from(e: russh_keys::Error) -> Self {
+        Self::Key(e)
+    }
+}
+
+impl From<String> for EngineError {
+    fn from(message: String) -> Self {
+        Self::Invalid(message)
+    }
+}
@@ -0,0 +1,48 @@
+use glm::{Mat4, Vec3, Vec4};
+
+use crate::bounds::{Aabb, BoundingSphere};
+
+// The six planes of a view frustum, with normals pointing inwards.
+#[derive(Debug, Clone)]
+pub struct Frustum {
+    pub planes: [Vec4; 6]
+}
+
+impl Frustum {
+    // Extracts the planes from a projection matrix (Gribb/Hartmann). With a view projection matrix the
+    // planes are in world space, with a model view projection matrix they are in the model's space.
+    pub fn from_matrix(matrix: &Mat4) -> Self {
+        let row = |i: usize| -> Vec4 { matrix.row(i).transpose() };
+
+        let planes = [
+            row(3) + row(0), // Left
+            row(3) - row(0), // Right
+            row(3) + row(1), // Bottom
+            row(3) - row(1), // Top
+            row(3) + row(2), // Near
+            row(3) - row(2)  // Far
+        ].map(|p| p / p.xyz().norm().max(f32::EPSILON));
+
+        Self { planes }
+    }
+
+    fn distance(plane: &Vec4, point: &Vec3) -> f32 {
+        plane.xyz().dot(point) + plane.w
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|p| Self::distance(p, &sphere.center) >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|p| {
+            // Corner furthest along the plane normal.
+            let corner = Vec3::from_fn(|i, _| if p[i] >= 0.0 { aabb.max[i] } else { aabb.min[i] });
+            Self::distance(p, &corner) >= 0.0
+        })
+    }
+
+    pub fn contains_point(&self, point: &Vec3) -> bool {
+        self.planes.iter().all(|p| Self::distance(p, point) >= 0.0)
+    }
+}
@@ -0,0 +1,130 @@
+use std::{fs, path::Path};
+
+use glm::{mat4_to_mat3, vec3, Mat4};
+
+use crate::{camera::Camera, char::CharColor, mesh::Mesh, preview::{bounding_sphere, preview_shader}, raster::{CellMode, Framebuf}, texture::Texture};
+
+// Golden-image regression tests: every bundled OBJ asset is rendered from a fixed camera and
+// compared against the reference images in `assets/golden`. Run with `terminal-engine golden`,
+// add `--bless` to regenerate the references after an intended change.
+
+const ASSETS: [&str; 6] = ["bull", "cube", "low-poly-skull", "low-poly-torus", "statue", "torus"];
+const GOLDEN_DIR: &str = "assets/golden";
+const ACTUAL_DIR: &str = "target/golden";
+
+const BACKGROUND: CharColor = CharColor { r: 0, g: 0, b: 0 };
+// Largest per channel difference for two pixels to count as equal.
+const CHANNEL_TOLERANCE: u8 = 8;
+// Fraction of pixels allowed to differ, absorbs rounding differences along edges.
+const PIXEL_TOLERANCE: f32 = 0.005;
+
+pub fn run(bless: bool) -> bool {
+    let mut passed = true;
+
+    for name in ASSETS {
+        let meshes = match Mesh::load_obj(format!("assets/{name}.obj")) {
+            Ok(result) => result,
+            Err(err) => {
+                println!("{name}: FAILED to load ({err})");
+                passed = false;
+                continue;
+            }
+        };
+
+        let fb = render(&meshes);
+        let images = [
+            (format!("{name}.png"), fb.pixel_image(BACKGROUND)),
+            (format!("{name}.cells.png"), fb.cell_image(2, 4, BACKGROUND))
+        ];
+
+        for (file, actual) in images {
+            let expected_path = Path::new(GOLDEN_DIR).join(&file);
+
+            if bless {
+                let result = fs::create_dir_all(GOLDEN_DIR).map_err(|e| e.to_string())
+                    .and_then(|_| actual.write_png(&expected_path).map_err(|e| e.to_string()));
+
+                match result {
+                    Ok(_) => println!("{file}: blessed"),
+                    Err(err) => {
+                        println!("{file}: FAILED to write ({err})");
+                        passed = false;
+                    }
+                }
+
+                continue;
+            }
+
+            let result = match Texture::load_png(&expected_path) {
+                Ok(expected) => compare(&expected, &actual),
+                Err(err) => Err(format!("missing reference ({err})"))
+            };
+
+            match result {
+                Ok(_) => println!("{file}: ok"),
+                Err(err) => {
+                    // Keep the actual image around so it can be inspected next to the reference.
+                    let actual_path = Path::new(ACTUAL_DIR).join(&file);
+                    let _ = fs::create_dir_all(ACTUAL_DIR);
+                    let _ = actual.write_png(&actual_path);
+
+                    println!("{file}: FAILED, {err}, actual image written to {}", actual_path.display());
+                    passed = false;
+                }
+            }
+        }
+    }
+
+    passed
+}
+
+fn compare(expected: &Texture, actual: &Texture) -> Result<(), String> {
+    if (expected.w, expected.h) != (actual.w, actual.h) {
+        return Err(format!("size {}x{} differs from {}x{}", actual.w, actual.h, expected.w, expected.h));
+    }
+
+    let differing = expected.pixels.iter().zip(&actual.pixels)
+        .filter(|(a, b)| a.r.abs_diff(b.r).max(a.g.abs_diff(b.g)).max(a.b.abs_diff(b.b)) > CHANNEL_TOLERANCE)
+        .count();
+
+    if differing as f32 > PIXEL_TOLERANCE * expected.pixels.len() as f32 {
+        return Err(format!("{differing} of {} pixels differ", expected.pixels.len()));
+    }
+
+    Ok(())
+}
+
+fn render(meshes: &[Mesh]) -> Framebuf {
+    let mut fb = Framebuf::new(48, 48);
+    fb.cell_mode = CellMode::Braille;
+    fb.clear();
+
+    // Frame the model by its bounding sphere.
+    let (center, radius) = bounding_sphere(meshes);
+    let camera = Camera {
+        position: center + vec3(radius, -radius, radius * 2.0),
+        target: center,
+        near: radius * 0.1,
+        far: radius * 10.0,
+        ..Default::default()
+    };
+
+    let model_matrix = Mat4::identity();
+    let vp_matrix = camera.fb_proj_matrix(&fb) * camera.view_matrix();
+
+    for mesh in meshes {
+        fb.draw_model(mesh, &model_matrix, &vp_matrix, &mat4_to_mat3(&model_matrix), &camera.position, &preview_shader);
+    }
+
+    fb.resolve();
+    fb
+}
+
+#[cfg(test)]
+mod tests {
+    // Regenerate the references with `terminal-engine golden --bless` after an intended change.
+    #[test]
+    fn bundled_assets_match_golden_images() {
+        assert!(super::run(false), "rendered images differ from the references in {}", super::GOLDEN_DIR);
+    }
+}
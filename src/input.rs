@@ -0,0 +1,167 @@
+// Keys decoded from the raw byte stream a terminal sends, the same for SSH clients and the local TTY.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    // Ctrl + letter, stored lowercase.
+    Ctrl(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    // Not a key press but the terminal's reply to `ANSI_QUERY_CELL_SIZE`, in pixels.
+    CellSize { width: u16, height: u16 },
+    Unknown
+}
+
+impl Key {
+    pub fn is_quit(&self) -> bool {
+        matches!(self, Key::Char('q') | Key::Ctrl('c'))
+    }
+}
+
+// Longest unfinished escape sequence held back for the next read, anything longer is garbage.
+const MAX_PENDING: usize = 32;
+
+// Decodes keys from a stream of reads, carrying escape sequences split across them over to the next read.
+#[derive(Debug, Default)]
+pub struct KeyReader {
+    pending: Vec<u8>
+}
+
+impl KeyReader {
+    pub fn feed(&mut self, data: &[u8]) -> Vec<Key> {
+        self.pending.extend_from_slice(data);
+
+        let rest = self.pending.split_off(incomplete_start(&self.pending));
+        let keys = parse_keys(&self.pending);
+        self.pending = rest;
+        keys
+    }
+}
+
+pub fn parse_keys(data: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let (key, len) = parse_key(&data[i..]);
+        keys.push(key);
+        i += len;
+    }
+
+    keys
+}
+
+// Decodes the first key and returns how many bytes it used.
+fn parse_key(data: &[u8]) -> (Key, usize) {
+    match data {
+        // XTWINOPS cell size report, ESC [ 6 ; height ; width t.
+        [0x1b, b'[', b'6', b';', ..] if data[csi_len(data) - 1] == b't' => {
+            let len = csi_len(data);
+            let params = std::str::from_utf8(&data[4..len - 1]).unwrap_or_default();
+
+            match params.split_once(';').map(|(h, w)| (h.parse(), w.parse())) {
+                Some((Ok(height), Ok(width))) => (Key::CellSize { width, height }, len),
+                _ => (Key::Unknown, len)
+            }
+        },
+        // CSI (ESC [) and SS3 (ESC O) arrow keys, the latter is sent in application cursor mode.
+        [0x1b, b'[' | b'O', code, ..] => match code {
+            b'A' => (Key::Up, 3),
+            b'B' => (Key::Down, 3),
+            b'C' => (Key::Right, 3),
+            b'D' => (Key::Left, 3),
+            _ => (Key::Unknown, csi_len(data))
+        },
+        [0x1b, ..] => (Key::Escape, 1),
+        [b'\r' | b'\n', ..] => (Key::Enter, 1),
+        [b'\t', ..] => (Key::Tab, 1),
+        [0x7f | 0x08, ..] => (Key::Backspace, 1),
+        [c @ 0x01..=0x1a, ..] => (Key::Ctrl((b'a' + c - 1) as char), 1),
+        [c, ..] => {
+            let len = utf8_len(*c).min(data.len());
+
+            match std::str::from_utf8(&data[..len]).ok().and_then(|s| s.chars().next()) {
+                Some(c) => (Key::Char(c), len),
+                None => (Key::Unknown, 1)
+            }
+        },
+        [] => (Key::Unknown, 0)
+    }
+}
+
+// Where a CSI sequence still missing its final byte starts at the end of `data`, `data.len()` if there is none.
+// A lone ESC is the Escape key, terminals send whole sequences in one write.
+fn incomplete_start(data: &[u8]) -> usize {
+    let Some(start) = data.iter().rposition(|b| *b == 0x1b) else {
+        return data.len();
+    };
+
+    match &data[start..] {
+        [0x1b, b'[', params @ ..] if params.len() < MAX_PENDING && !params.iter().any(|b| (0x40..=0x7e).contains(b)) => start,
+        _ => data.len()
+    }
+}
+
+// Length of an escape sequence we don't understand, up to and including its final byte.
+fn csi_len(data: &[u8]) -> usize {
+    data.iter().skip(2).position(|b| (0x40..=0x7e).contains(b)).map_or(data.len(), |i| i + 3)
+}
+
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0xf0..=0xff => 4,
+        0xe0..=0xef => 3,
+        0xc0..=0xdf => 2,
+        _ => 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arrows_in_csi_and_ss3_form() {
+        assert_eq!(parse_keys(b"\x1b[A\x1b[B\x1bOC\x1bOD"), [Key::Up, Key::Down, Key::Right, Key::Left]);
+    }
+
+    #[test]
+    fn ctrl_letters() {
+        assert_eq!(parse_keys(b"\x01\x03\x1a"), [Key::Ctrl('a'), Key::Ctrl('c'), Key::Ctrl('z')]);
+        assert!(Key::Ctrl('c').is_quit());
+    }
+
+    #[test]
+    fn lone_escape() {
+        assert_eq!(parse_keys(b"\x1b"), [Key::Escape]);
+        assert_eq!(KeyReader::default().feed(b"\x1b"), [Key::Escape]);
+        assert_eq!(parse_keys(b"\x1bq"), [Key::Escape, Key::Char('q')]);
+    }
+
+    #[test]
+    fn sequence_split_across_reads() {
+        let mut reader = KeyReader::default();
+
+        assert_eq!(reader.feed(b"a\x1b["), [Key::Char('a')]);
+        assert_eq!(reader.feed(b"A"), [Key::Up]);
+
+        assert_eq!(reader.feed(b"\x1b[6;1"), []);
+        assert_eq!(reader.feed(b"8;9tb"), [Key::CellSize { width: 9, height: 18 }, Key::Char('b')]);
+    }
+
+    #[test]
+    fn cell_size_reply() {
+        assert_eq!(parse_keys(b"\x1b[6;20;10t"), [Key::CellSize { width: 10, height: 20 }]);
+        assert_eq!(parse_keys(b"\x1b[6;20t+"), [Key::Unknown, Key::Char('+')]);
+    }
+
+    #[test]
+    fn utf8_and_unknown_sequences() {
+        assert_eq!(parse_keys("é\x1b[1;5Aq".as_bytes()), [Key::Char('é'), Key::Unknown, Key::Char('q')]);
+    }
+}
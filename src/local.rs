@@ -0,0 +1,93 @@
+use std::{io::{self, Read, Write}, time::{Duration, Instant}};
+
+use crossterm::{execute, terminal::{self, disable_raw_mode, enable_raw_mode, window_size, EnterAlternateScreen, LeaveAlternateScreen}};
+use tokio::sync::mpsc;
+
+use crate::{char::{AnsiColorMode, ANSI_CLEAR_SCREEN, ANSI_HIDE_CURSOR, ANSI_MOVE_CURSOR_TO_START, ANSI_QUERY_CELL_SIZE, ANSI_RESET, ANSI_SHOW_CURSOR}, client::fit_to_terminal, demo::Demo, input::KeyReader, raster::Framebuf};
+
+// Puts the TTY into raw mode on the alternate screen and restores it when dropped, also on panics.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+        print!("{ANSI_RESET}{ANSI_SHOW_CURSOR}");
+        let _ = io::stdout().flush();
+        let _ = disable_raw_mode();
+    }
+}
+
+// Single-player mode: renders the demo straight into the current terminal instead of over SSH.
+pub async fn run(demo: Demo, mut fb: Framebuf) -> io::Result<()> {
+    let _terminal = RawTerminal::enter()?;
+
+    // Stdin reads block, so they happen on their own thread. The bytes go through
+    // the same key parsing as the data SSH clients send.
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+
+    std::thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut buf = [0; 64];
+
+        while let Ok(len @ 1..) = stdin.read(&mut buf) {
+            if sender.send(buf[..len].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut camera = demo.camera.clone();
+    let start_time = Instant::now();
+    let mut stdout = io::stdout();
+
+    // Not every platform knows the window's pixel size, the query reply covers the rest.
+    if let Ok(size) = window_size() {
+        camera.set_cell_aspect_from_pixels(size.width.into(), size.height.into(), size.columns.into(), size.rows.into());
+    }
+
+    stdout.write_all(ANSI_QUERY_CELL_SIZE.as_bytes())?;
+
+    let mut term_size = (0, 0);
+    let mut keys = KeyReader::default();
+
+    loop {
+        // crossterm only reports `Event::Resize` to its own event reader, which would take
+        // stdin away from `parse_keys`, so the size is checked every frame instead.
+        let size = terminal::size()?;
+
+        if size != term_size {
+            term_size = size;
+            fit_to_terminal(&mut fb, (size.0.into(), size.1.into()));
+            stdout.write_all(ANSI_CLEAR_SCREEN.as_bytes())?;
+        }
+
+        while let Ok(data) = receiver.try_recv() {
+            for key in keys.feed(&data) {
+                if key.is_quit() {
+                    return Ok(());
+                }
+
+                camera.handle_key(&key);
+            }
+        }
+
+        demo.draw_view(&mut fb, &camera, &[], start_time.elapsed().as_millis() as f32);
+
+        let mut str = format!("{ANSI_HIDE_CURSOR}{ANSI_MOVE_CURSOR_TO_START}");
+        str.push_str(&fb.to_string(&AnsiColorMode::AnsiTrueColor));
+        str.push_str("Arrow keys orbit, +/- zoom, q quits\r\n");
+
+        stdout.write_all(str.as_bytes())?;
+        stdout.flush()?;
+
+        tokio::time::sleep(Duration::from_millis(16)).await;
+    }
+}
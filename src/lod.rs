@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use glm::Mat4;
+
+use crate::{bounds::BoundingSphere, camera::Camera, error::EngineError, mesh::Mesh};
+
+#[derive(Debug, Clone)]
+pub struct LodLevel {
+    pub mesh: Mesh,
+    // Smallest projected height in cells this level is used at.
+    pub min_size: f32
+}
+
+// Variants of the same object from detailed to cheap, picked by how large the object appears on screen.
+#[derive(Debug, Clone)]
+pub struct LodGroup {
+    levels: Vec<LodLevel>,
+    // Shared by all levels so every variant projects to the same size.
+    sphere: BoundingSphere,
+    // Fraction the size has to pass a threshold by before switching, so objects
+    // sitting right at a threshold don't flicker between levels.
+    pub hysteresis: f32,
+    current: usize
+}
+
+impl LodGroup {
+    // Levels are sorted by `min_size`, the most detailed level's bounds are used for all of them.
+    pub fn new(mut levels: Vec<LodLevel>) -> Result<Self, EngineError> {
+        levels.sort_by(|a, b| b.min_size.total_cmp(&a.min_size));
+
+        let sphere = levels.first().ok_or_else(|| EngineError::Invalid("LOD group needs at least one level".to_owned()))?.mesh.sphere;
+
+        Ok(Self {
+            sphere,
+            levels,
+            hysteresis: 0.15,
+            current: 0
+        })
+    }
+
+    // Loads the first object of each OBJ file as a level, e.g. [("torus.obj", 12.0), ("low-poly-torus.obj", 0.0)].
+    pub fn load<P: AsRef<Path>>(levels: &[(P, f32)]) -> Result<Self, EngineError> {
+        let levels = levels.iter()
+            .map(|(path, min_size)| Mesh::load_obj_first(path)
+                .map(|mesh| LodLevel { mesh, min_size: *min_size })
+                .map_err(|e| EngineError::asset(path, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Self::new(levels)
+    }
+
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    // Approximate height in cells of the object's bounding sphere seen by the camera on `rows` cell rows.
+    pub fn projected_size(&self, model_matrix: &Mat4, camera: &Camera, rows: usize) -> f32 {
+        let sphere = self.sphere.transform(model_matrix);
+        let distance = (sphere.center - camera.position).norm();
+
+        // The camera is inside the sphere, so the object covers the whole view.
+        if distance <= sphere.radius {
+            return f32::INFINITY;
+        }
+
+        sphere.radius / (distance * (camera.fov / 2.0).tan()) * rows as f32
+    }
+
+    // Picks the level for an object of `size` cells, moving at most as far as the hysteresis allows.
+    pub fn select(&mut self, size: f32) -> &Mesh {
+        while self.current > 0 && size >= self.levels[self.current - 1].min_size * (1.0 + self.hysteresis) {
+            self.current -= 1;
+        }
+
+        while self.current + 1 < self.levels.len() && size < self.levels[self.current].min_size * (1.0 - self.hysteresis) {
+            self.current += 1;
+        }
+
+        &self.levels[self.current].mesh
+    }
+
+    // The mesh to draw this frame for an object at `model_matrix`.
+    pub fn mesh_for(&mut self, model_matrix: &Mat4, camera: &Camera, rows: usize) -> &Mesh {
+        let size = self.projected_size(model_matrix, camera, rows);
+        self.select(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glm::vec3;
+
+    use super::*;
+
+    fn level(min_size: f32) -> LodLevel {
+        let positions = vec![-1.0, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0];
+        LodLevel { mesh: Mesh::new(format!("{min_size}"), positions, Vec::new(), Vec::new(), vec![0, 1, 2]), min_size }
+    }
+
+    // Camera on the z axis at the distance the object appears `size` cells tall on 40 rows.
+    fn camera_for(group: &LodGroup, size: f32) -> Camera {
+        let camera = Camera::default();
+        let distance = group.sphere.radius * 40.0 / (size * (camera.fov / 2.0).tan());
+
+        Camera { position: group.sphere.center + vec3(0.0, 0.0, distance), target: group.sphere.center, ..camera }
+    }
+
+    #[test]
+    fn empty_group_is_rejected() {
+        assert!(LodGroup::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn sizes_inside_the_hysteresis_band_keep_the_level() {
+        let mut group = LodGroup::new(vec![level(0.0), level(12.0)]).unwrap();
+        let mut level_at = |size: f32| {
+            let camera = camera_for(&group, size);
+            group.mesh_for(&Mat4::identity(), &camera, 40).name.clone()
+        };
+
+        assert_eq!(level_at(20.0), "12");
+
+        // Back and forth across the 12 cell threshold, but within 15% of it.
+        for size in [11.0, 12.5, 10.5, 13.5, 11.5] {
+            assert_eq!(level_at(size), "12", "size {size}");
+        }
+
+        assert_eq!(level_at(10.0), "0");
+
+        for size in [12.5, 11.0, 13.5, 10.5, 12.0] {
+            assert_eq!(level_at(size), "0", "size {size}");
+        }
+
+        assert_eq!(level_at(14.0), "12");
+    }
+
+    #[test]
+    fn large_jumps_skip_levels() {
+        let mut group = LodGroup::new(vec![level(4.0), level(0.0), level(16.0)]).unwrap();
+
+        assert_eq!(group.levels().iter().map(|l| l.min_size).collect::<Vec<_>>(), [16.0, 4.0, 0.0]);
+        assert_eq!(group.select(1.0).name, "0");
+        assert_eq!(group.current(), 2);
+        assert_eq!(group.select(100.0).name, "16");
+        assert_eq!(group.current(), 0);
+    }
+}
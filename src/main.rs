@@ -1,80 +1,128 @@
-use std::time::{Duration, SystemTime};
+use std::{sync::Arc, time::{Duration, Instant}};
 
-use char::{AnsiColorMode, CharColor, CharInfo};
-use glm::{look_at, make_vec2, make_vec3, mat4_to_mat3, perspective, rotate, scale, vec3, vec4_to_vec3, Vec3};
-use raster::{half_block_shader, CharHalf, Framebuf};
+use capture::{render_gif, GifOptions};
+use demo::Demo;
+use error::EngineError;
+use raster::Framebuf;
 use server::SshSession;
-use vertex::Vertex;
 
+pub mod error;
 pub mod raster;
 pub mod char;
+pub mod color;
 pub mod vertex;
 pub mod clip;
+pub mod bounds;
+pub mod frustum;
+pub mod bvh;
+pub mod mesh;
+pub mod anim;
+pub mod morph;
+pub mod scene;
+pub mod stl;
+pub mod ply;
+pub mod lod;
+pub mod stencil;
+pub mod texture;
+pub mod background;
+pub mod post;
 pub mod server;
 pub mod client;
+pub mod camera;
+pub mod input;
+pub mod local;
+pub mod record;
+pub mod capture;
+pub mod demo;
+pub mod golden;
+pub mod preview;
 
 extern crate nalgebra_glm as glm;
 
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1))
+}
+
+fn parse_arg<T: std::str::FromStr>(args: &[String], name: &str, default: T) -> Result<T, EngineError> {
+    arg_value(args, name).map_or(Ok(default), |v| v.parse().map_err(|_| EngineError::Invalid(format!("Invalid value '{v}' for {name}"))))
+}
+
 #[tokio::main]
 async fn main() {
-    let start_time = SystemTime::now();
+    let args = std::env::args().collect::<Vec<_>>();
+
+    if args.get(1).map(String::as_str) == Some("golden") {
+        let passed = golden::run(args.iter().any(|a| a == "--bless"));
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    if args.get(1).map(String::as_str) == Some("render") {
+        if let Err(err) = preview::run(&args[2..]) {
+            eprintln!("{err}\n{}", preview::USAGE);
+            std::process::exit(1);
+        }
+
+        return;
+    }
 
-    let (models, _materials) = tobj::load_obj("assets/low-poly-skull.obj", &tobj::GPU_LOAD_OPTIONS)
-        .expect("Failed to load file");
+    if let Err(err) = run(&args).await {
+        eprintln!("Error: {err}");
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: &[String]) -> Result<(), EngineError> {
+    let demo = Demo::load()?;
+
+    // Usage: gif <path> [--frames N] [--frame-time MS] [--scale S]
+    if args.get(1).map(String::as_str) == Some("gif") {
+        let path = args.get(2).ok_or_else(|| EngineError::Invalid("Missing output path".to_owned()))?;
+        let defaults = GifOptions::default();
+        let options = GifOptions {
+            frames: parse_arg(args, "--frames", defaults.frames)?,
+            frame_time: parse_arg(args, "--frame-time", defaults.frame_time)?,
+            scale: parse_arg(args, "--scale", defaults.scale)?,
+            ..defaults
+        };
+
+        let mut fb = Framebuf::new(48, 48);
+        fb.set_samples(2);
 
-    let mut fb = Framebuf::new(48, 48);
+        render_gif(path, &mut fb, &options, |fb, _, time| demo.draw(fb, time))?;
+        println!("Wrote {} frames to {path}", options.frames);
+        return Ok(());
+    }
 
-    let camera_pos = vec3(0.0, -0.5, 4.0);
-    let proj_matrix = perspective(fb.h as f32 / fb.w as f32, 70.0, 0.0001, 1000.0);
-    let view_matrix = look_at(&camera_pos, &vec3(0.0, -0.5, 0.0), &vec3(0.0, -1.0, 0.0));
+    if args.get(1).map(String::as_str) == Some("local") {
+        // Sized to the terminal once it's running.
+        let mut fb = Framebuf::new(0, 0);
+        fb.set_samples(2);
+
+        return Ok(local::run(demo, fb).await?);
+    }
+
+    let start_time = Instant::now();
 
     let mut server = SshSession::new();
-    let server_clone = server.clone();
+    server.spawn_camera = demo.camera.clone();
+
+    if let Some(dir) = arg_value(args, "--record") {
+        server.record_all(dir)?;
+        println!("Recording sessions to {dir}");
+    }
+
+    let mut server_clone = server.clone();
+    let demo = Arc::new(demo);
 
     tokio::spawn(async move {
         loop {
             tokio::time::sleep(Duration::from_millis(5)).await;
 
-            fb.clear();
-
-            let elapsed_time = start_time.elapsed().unwrap().as_millis() as f32;
-            
-            let mut model_matrix = rotate(&glm::identity(), elapsed_time / 600.0, &vec3(0.0, 1.0, 0.0));
-            //model_matrix = rotate(&model_matrix, elapsed_time / 1500.0, &vec3(1.0, 0.0, 0.0));
-            model_matrix = scale(&model_matrix, &vec3(1.8, 1.8, 1.8));
-
-            let vp_matrix = proj_matrix * view_matrix;
-            let normal_matrix = mat4_to_mat3(&model_matrix.try_inverse().unwrap().transpose());
-
-            fb.draw_model(&models[0], &model_matrix, &vp_matrix, &normal_matrix, &camera_pos, |vertex: &Vertex, c: &mut CharInfo, half: &CharHalf| {
-                let light_color = vec3(1.0, 1.0, 1.0);
-                let light_pos = vec3(100.0, 0.0, 50.0);
-                let light_direction = (light_pos - vec4_to_vec3(&vertex.position)).normalize();
-                
-                let ambient_strength = 0.0;
-                let ambient = ambient_strength * light_color;
-                
-                let normal = make_vec3(&vertex.attributes[2..5]);
-                let diffuse = normal.dot(&light_direction).max(0.0) * light_color;
-
-                let tex_coord = make_vec2(&vertex.attributes[0..2]);
-                let size = 8.0;
-                let pattern: bool = ((tex_coord[0] * size % 1.0) > 0.5) ^ ((tex_coord[1] * size % 1.0) < 0.5);
-                let object_color = if pattern { vec3(0.8, 0.85, 1.0) } else { vec3(0.2, 0.6, 0.8) };
-                let result = (ambient + diffuse).component_mul(&object_color) + vec3(0.2, 0.2, 0.2);
-                
-                half_block_shader(c, &half, 
-                    &CharColor { r: (result.x * 255.0) as u8, g: (result.y * 255.0) as u8, b: (result.z * 255.0) as u8 });
-            });
-
-            let mut str = "\x1b[?25l\x1b[H".to_owned(); // Make cursor invisible and move cursor to beginning.
-            str.push_str(&fb.to_string(&AnsiColorMode::AnsiTrueColor));
-            str.push_str(&format!("Users online: {}\r\n", server_clone.num_sessions().await));
-            
-            server_clone.clone().broadcast_message(str).await;
+            let elapsed_time = start_time.elapsed().as_millis() as f32;
+            let demo = demo.clone();
+            server_clone.render_all(move |fb, camera, players| demo.draw_view(fb, camera, players, elapsed_time)).await;
         }
     });
 
-    println!("SSH server started and listening on port 22");
-    server.run().await;
+    server.run().await
 }
@@ -0,0 +1,366 @@
+use std::{collections::HashMap, error::Error, fmt, path::Path};
+
+use glm::{make_vec3, Mat4, Vec3};
+use tobj::Model;
+
+use crate::{bounds::{Aabb, BoundingSphere}, frustum::Frustum, morph::MorphTarget};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalMode {
+    // Averaged over adjacent faces, for curved surfaces.
+    Smooth,
+    // One normal per face, vertices get split.
+    Flat
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    Load(tobj::LoadError),
+    // The file loaded but had no objects.
+    Empty(String),
+    IndexOutOfRange { mesh: String, attribute: &'static str, index: u32, count: usize },
+    IncompleteTriangle { mesh: String, indices: usize },
+    AttributeCount { mesh: String, attribute: &'static str, count: usize, expected: usize }
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Load(e) => write!(f, "{e}"),
+            Self::Empty(path) => write!(f, "{path} contains no meshes"),
+            Self::IndexOutOfRange { mesh, attribute, index, count } =>
+                write!(f, "Mesh '{mesh}' refers to {attribute} {index}, but there are only {count}"),
+            Self::IncompleteTriangle { mesh, indices } =>
+                write!(f, "Mesh '{mesh}' has {indices} indices, which isn't a whole number of triangles"),
+            Self::AttributeCount { mesh, attribute, count, expected } =>
+                write!(f, "Mesh '{mesh}' has {count} {attribute} values, expected {expected}")
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+impl From<tobj::LoadError> for MeshError {
+    fn from(e: tobj::LoadError) -> Self {
+        Self::Load(e)
+    }
+}
+
+// Triangle mesh with one index shared by all attributes, ready for `Framebuf::draw_model`.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    pub name: String,
+    // Flat xyz positions and normals, uv texture coordinates.
+    pub positions: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub texcoords: Vec<f32>,
+    // Flat linear rgb per vertex, empty means white.
+    pub colors: Vec<f32>,
+    pub indices: Vec<u32>,
+    // Four joint indices and weights per vertex for skinned meshes, empty otherwise.
+    pub joints: Vec<u16>,
+    pub weights: Vec<f32>,
+    // Index into the materials of the scene the mesh was loaded with.
+    pub material: Option<usize>,
+    // Blend shapes and their weights when no animation drives them.
+    pub morph_targets: Vec<MorphTarget>,
+    pub morph_weights: Vec<f32>,
+    // Bounds in model space, computed at load and used for culling.
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere
+}
+
+impl Mesh {
+    pub fn new(name: String, positions: Vec<f32>, normals: Vec<f32>, texcoords: Vec<f32>, indices: Vec<u32>) -> Self {
+        Self {
+            name,
+            aabb: Aabb::from_positions(&positions),
+            sphere: BoundingSphere::from_positions(&positions),
+            positions,
+            normals,
+            texcoords,
+            colors: Vec::new(),
+            indices,
+            joints: Vec::new(),
+            weights: Vec::new(),
+            material: None,
+            morph_targets: Vec::new(),
+            morph_weights: Vec::new()
+        }
+    }
+
+    // OBJ models may index positions, normals and texture coordinates separately, every distinct
+    // combination becomes one vertex. Missing normals and texture coordinates are generated.
+    pub fn from_model(model: &Model) -> Result<Self, MeshError> {
+        let source = &model.mesh;
+        let vertex_count = source.positions.len() / 3;
+
+        let check = |attribute: &'static str, indices: &[u32], count: usize| -> Result<(), MeshError> {
+            match indices.iter().find(|i| **i as usize >= count) {
+                Some(index) => Err(MeshError::IndexOutOfRange { mesh: model.name.clone(), attribute, index: *index, count }),
+                None => Ok(())
+            }
+        };
+
+        check("position", &source.indices, vertex_count)?;
+        check("normal", &source.normal_indices, source.normals.len() / 3)?;
+        check("texture coordinate", &source.texcoord_indices, source.texcoords.len() / 2)?;
+
+        let mut mesh = Self::new(model.name.clone(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let mut vertices = HashMap::new();
+
+        let has_normals = !source.normals.is_empty() && source.normal_indices.len() == source.indices.len();
+        let has_texcoords = !source.texcoords.is_empty() && source.texcoord_indices.len() == source.indices.len();
+        let has_colors = source.vertex_color.len() == source.positions.len() && !source.vertex_color.is_empty();
+
+        for (k, position) in source.indices.iter().map(|i| *i as usize).enumerate() {
+            let normal = has_normals.then(|| source.normal_indices[k] as usize);
+            let texcoord = has_texcoords.then(|| source.texcoord_indices[k] as usize);
+
+            let index = *vertices.entry((position, normal, texcoord)).or_insert_with(|| {
+                mesh.positions.extend_from_slice(&source.positions[3 * position..3 * position + 3]);
+
+                if let Some(n) = normal {
+                    mesh.normals.extend_from_slice(&source.normals[3 * n..3 * n + 3]);
+                }
+
+                if let Some(t) = texcoord {
+                    mesh.texcoords.extend_from_slice(&source.texcoords[2 * t..2 * t + 2]);
+                }
+
+                if has_colors {
+                    mesh.colors.extend_from_slice(&source.vertex_color[3 * position..3 * position + 3]);
+                }
+
+                (mesh.positions.len() / 3 - 1) as u32
+            });
+
+            mesh.indices.push(index);
+        }
+
+        mesh.generate_missing(NormalMode::Smooth);
+        mesh.update_bounds();
+        mesh.validate()?;
+        Ok(mesh)
+    }
+
+    // Loads every object of an OBJ file as its own mesh.
+    pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<Self>, MeshError> {
+        let options = tobj::LoadOptions {
+            triangulate: true,
+            ignore_points: true,
+            ignore_lines: true,
+            ..Default::default()
+        };
+
+        let (models, _materials) = tobj::load_obj(path.as_ref(), &options)?;
+        models.iter().map(Self::from_model).collect()
+    }
+
+    // The first object of an OBJ file.
+    pub fn load_obj_first<P: AsRef<Path>>(path: P) -> Result<Self, MeshError> {
+        Self::load_obj(path.as_ref())?.into_iter().next()
+            .ok_or_else(|| MeshError::Empty(path.as_ref().display().to_string()))
+    }
+
+    // Checks that the indices and attributes fit together, so drawing can't index out of bounds.
+    pub fn validate(&self) -> Result<(), MeshError> {
+        let vertex_count = self.positions.len() / 3;
+        let attribute_count = |attribute: &'static str, len: usize, per_vertex: usize, optional: bool| {
+            if len == vertex_count * per_vertex || (optional && len == 0) {
+                Ok(())
+            } else {
+                Err(MeshError::AttributeCount { mesh: self.name.clone(), attribute, count: len, expected: vertex_count * per_vertex })
+            }
+        };
+
+        attribute_count("position", self.positions.len(), 3, false)?;
+
+        if !self.indices.len().is_multiple_of(3) {
+            return Err(MeshError::IncompleteTriangle { mesh: self.name.clone(), indices: self.indices.len() });
+        }
+
+        if let Some(index) = self.indices.iter().find(|i| **i as usize >= vertex_count) {
+            return Err(MeshError::IndexOutOfRange { mesh: self.name.clone(), attribute: "position", index: *index, count: vertex_count });
+        }
+
+        attribute_count("normal", self.normals.len(), 3, false)?;
+        attribute_count("texture coordinate", self.texcoords.len(), 2, false)?;
+        attribute_count("color", self.colors.len(), 3, true)?;
+
+        // Skinning reads a joint and weight set for every vertex, so it needs both or neither.
+        attribute_count("joint", self.joints.len(), 4, self.weights.is_empty())?;
+        attribute_count("weight", self.weights.len(), 4, self.joints.is_empty())?;
+
+        for target in &self.morph_targets {
+            attribute_count("morph target position", target.position_deltas.len(), 3, true)?;
+            attribute_count("morph target normal", target.normal_deltas.len(), 3, true)?;
+        }
+
+        Ok(())
+    }
+
+    // Fills in normals and texture coordinates if the mesh has none.
+    pub fn generate_missing(&mut self, normals: NormalMode) {
+        if self.normals.len() != self.positions.len() {
+            match normals {
+                NormalMode::Smooth => self.compute_smooth_normals(),
+                NormalMode::Flat => self.compute_flat_normals()
+            }
+        }
+
+        if self.texcoords.len() != self.positions.len() / 3 * 2 {
+            self.compute_planar_texcoords();
+        }
+    }
+
+    // Whether any part of the bounds is inside the view frustum. The sphere is tested first
+    // in world space since it's cheaper, then the box in model space which fits tighter.
+    pub fn is_visible(&self, model_matrix: &Mat4, vp_matrix: &Mat4) -> bool {
+        Frustum::from_matrix(vp_matrix).intersects_sphere(&self.sphere.transform(model_matrix)) &&
+            Frustum::from_matrix(&(vp_matrix * model_matrix)).intersects_aabb(&self.aabb)
+    }
+
+    // Replaces the normals with area weighted averages of the adjacent face normals.
+    pub fn compute_smooth_normals(&mut self) {
+        let vertex_count = self.positions.len() / 3;
+        let position = |i: u32| make_vec3(&self.positions[3 * i as usize..3 * i as usize + 3]);
+        let mut normals = vec![Vec3::zeros(); vertex_count];
+
+        for indices in self.indices.chunks_exact(3).filter(|t| t.iter().all(|i| (*i as usize) < vertex_count)) {
+            let [a, b, c] = [position(indices[0]), position(indices[1]), position(indices[2])];
+            // Not normalized, so larger faces count more.
+            let normal = (b - a).cross(&(c - a));
+
+            for i in indices {
+                normals[*i as usize] += normal;
+            }
+        }
+
+        self.normals = normals.iter()
+            .map(|n| n.try_normalize(f32::EPSILON).unwrap_or_else(Vec3::zeros))
+            .flat_map(|n| [n.x, n.y, n.z])
+            .collect();
+    }
+
+    // Gives every triangle its own vertices with the face normal, for a faceted look.
+    pub fn compute_flat_normals(&mut self) {
+        let vertex_count = self.positions.len() / 3;
+
+        // Broken meshes are left as they are for `validate` to report.
+        if self.indices.iter().any(|i| *i as usize >= vertex_count) {
+            return;
+        }
+
+        let indices = std::mem::take(&mut self.indices);
+
+        // Copies the per-vertex values of every corner, in triangle order. Values that don't
+        // match the vertex count are kept, so `validate` still sees the mismatch.
+        let unweld = |values: &[f32], per_vertex: usize| -> Vec<f32> {
+            if values.len() != vertex_count * per_vertex {
+                return values.to_vec();
+            }
+
+            indices.iter().flat_map(|i| &values[*i as usize * per_vertex..(*i as usize + 1) * per_vertex]).copied().collect()
+        };
+
+        self.positions = unweld(&self.positions, 3);
+        self.texcoords = unweld(&self.texcoords, 2);
+        self.colors = unweld(&self.colors, 3);
+        self.weights = unweld(&self.weights, 4);
+
+        for target in &mut self.morph_targets {
+            target.position_deltas = unweld(&target.position_deltas, 3);
+            target.normal_deltas = unweld(&target.normal_deltas, 3);
+        }
+
+        if self.joints.len() == vertex_count * 4 {
+            self.joints = indices.iter().flat_map(|i| &self.joints[*i as usize * 4..*i as usize * 4 + 4]).copied().collect();
+        }
+
+        self.indices = (0..self.positions.len() as u32 / 3).collect();
+        self.normals = self.positions.chunks_exact(9)
+            .flat_map(|triangle| {
+                let [a, b, c] = [0, 3, 6].map(|i| make_vec3(&triangle[i..i + 3]));
+                let n = (b - a).cross(&(c - a)).try_normalize(f32::EPSILON).unwrap_or_else(Vec3::zeros);
+                [n.x, n.y, n.z].repeat(3)
+            })
+            .collect();
+    }
+
+    // Projects the positions onto the plane of the two largest extents of the bounds, scaled to 0..1.
+    pub fn compute_planar_texcoords(&mut self) {
+        let aabb = Aabb::from_positions(&self.positions);
+        let size = aabb.max - aabb.min;
+
+        let mut axes = [0, 1, 2];
+        axes.sort_by(|a, b| size[*b].total_cmp(&size[*a]));
+
+        let [u, v] = [axes[0], axes[1]];
+
+        self.texcoords = self.positions.chunks_exact(3)
+            .flat_map(|p| [
+                (p[u] - aabb.min[u]) / size[u].max(f32::EPSILON),
+                (p[v] - aabb.min[v]) / size[v].max(f32::EPSILON)
+            ])
+            .collect();
+    }
+
+    // Recomputes the bounds after the positions have been changed.
+    pub fn update_bounds(&mut self) {
+        self.aabb = Aabb::from_positions(&self.positions);
+        self.sphere = BoundingSphere::from_positions(&self.positions);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Mesh {
+        let mut mesh = Mesh::new("triangle".to_owned(), vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0], Vec::new(), Vec::new(), vec![0, 1, 2]);
+        mesh.generate_missing(NormalMode::Smooth);
+        mesh
+    }
+
+    #[test]
+    fn generated_attributes_are_valid() {
+        let mut flat = Mesh::new("quad".to_owned(), vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0], Vec::new(), Vec::new(), vec![0, 1, 2, 0, 2, 3]);
+        flat.joints = vec![0; 16];
+        flat.weights = vec![0.25; 16];
+        flat.generate_missing(NormalMode::Flat);
+
+        assert!(triangle().validate().is_ok());
+        assert!(flat.validate().is_ok());
+        assert_eq!(flat.joints.len(), 24);
+    }
+
+    #[test]
+    fn skin_attributes_must_match_the_vertex_count() {
+        let mut mesh = triangle();
+        mesh.joints = vec![0; 16];
+        mesh.weights = vec![1.0; 16];
+        assert!(matches!(mesh.validate(), Err(MeshError::AttributeCount { attribute: "joint", count: 16, expected: 12, .. })));
+
+        // Joints without weights can't be skinned either.
+        mesh.joints = vec![0; 12];
+        mesh.weights = Vec::new();
+        assert!(matches!(mesh.validate(), Err(MeshError::AttributeCount { attribute: "weight", count: 0, .. })));
+    }
+
+    #[test]
+    fn out_of_range_indices_survive_generation() {
+        let mut mesh = Mesh::new("broken".to_owned(), vec![0.0; 9], Vec::new(), Vec::new(), vec![0, 1, 5]);
+        mesh.generate_missing(NormalMode::Flat);
+
+        assert!(matches!(mesh.validate(), Err(MeshError::IndexOutOfRange { index: 5, count: 3, .. })));
+    }
+
+    #[test]
+    fn morph_targets_must_match_the_vertex_count() {
+        let mut mesh = triangle();
+        mesh.morph_targets.push(MorphTarget { position_deltas: vec![0.0; 6], normal_deltas: Vec::new() });
+
+        assert!(mesh.validate().is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::Path;
+
+use glm::{make_vec3, Vec3};
+
+use crate::{error::EngineError, mesh::Mesh};
+
+// Blend shape, offsets added to the base mesh scaled by the target's weight.
+#[derive(Debug, Clone, Default)]
+pub struct MorphTarget {
+    // Flat xyz per vertex, empty if the target doesn't move that attribute.
+    pub position_deltas: Vec<f32>,
+    pub normal_deltas: Vec<f32>
+}
+
+// Returns a copy of the mesh with its morph targets applied, e.g. for facial expressions.
+pub fn morph_mesh(mesh: &Mesh, weights: &[f32]) -> Mesh {
+    let mut morphed = mesh.clone();
+
+    for (target, weight) in mesh.morph_targets.iter().zip(weights).filter(|(_, w)| **w != 0.0) {
+        for (p, d) in morphed.positions.iter_mut().zip(&target.position_deltas) {
+            *p += d * weight;
+        }
+
+        for (n, d) in morphed.normals.iter_mut().zip(&target.normal_deltas) {
+            *n += d * weight;
+        }
+    }
+
+    normalize_normals(&mut morphed.normals);
+    morphed.update_bounds();
+    morphed
+}
+
+fn normalize_normals(normals: &mut [f32]) {
+    for n in normals.chunks_exact_mut(3) {
+        let normal = make_vec3(n).try_normalize(f32::EPSILON).unwrap_or_else(Vec3::zeros);
+        n.copy_from_slice(normal.as_slice());
+    }
+}
+
+// Pre-baked vertex animation, one mesh per frame with the same topology.
+#[derive(Debug, Clone)]
+pub struct VertexAnimation {
+    pub frames: Vec<Mesh>,
+    // Seconds per frame.
+    pub frame_time: f32
+}
+
+impl VertexAnimation {
+    pub fn new(frames: Vec<Mesh>, frame_time: f32) -> Result<Self, EngineError> {
+        let Some(first) = frames.first() else {
+            return Err(EngineError::Invalid("Vertex animation needs at least one frame".to_owned()));
+        };
+
+        if let Some(i) = frames.iter().position(|f| f.positions.len() != first.positions.len() || f.indices != first.indices) {
+            return Err(EngineError::Invalid(format!("Frame {i} of the vertex animation has a different topology than the first frame")));
+        }
+
+        Ok(Self { frames, frame_time })
+    }
+
+    // Loads the first object of every OBJ file as one frame, e.g. an export of every frame from Blender.
+    pub fn load_obj_sequence<P: AsRef<Path>>(paths: &[P], frame_time: f32) -> Result<Self, EngineError> {
+        let frames = paths.iter()
+            .map(|path| Mesh::load_obj_first(path).map_err(|e| EngineError::asset(path, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Self::new(frames, frame_time)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames.len() as f32 * self.frame_time
+    }
+
+    // The looping animation at `time` seconds, positions and normals are interpolated between frames.
+    pub fn frame_at(&self, time: f32) -> Mesh {
+        let position = time.rem_euclid(self.duration().max(f32::EPSILON)) / self.frame_time.max(f32::EPSILON);
+        let a = &self.frames[(position as usize).min(self.frames.len() - 1)];
+        let b = &self.frames[(position as usize + 1) % self.frames.len()];
+        let t = position.fract();
+
+        let mut mesh = a.clone();
+
+        for (value, (a, b)) in mesh.positions.iter_mut().zip(a.positions.iter().zip(&b.positions)) {
+            *value = a + (b - a) * t;
+        }
+
+        for (value, (a, b)) in mesh.normals.iter_mut().zip(a.normals.iter().zip(&b.normals)) {
+            *value = a + (b - a) * t;
+        }
+
+        normalize_normals(&mut mesh.normals);
+        mesh.update_bounds();
+        mesh
+    }
+}
@@ -0,0 +1,289 @@
+use std::{fs, io, path::Path};
+
+use crate::{color::srgb_to_linear, error::invalid_data, mesh::{Mesh, NormalMode}};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8, U8, I16, U16, I32, U32, F32, F64
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(invalid_data(format!("Unknown PLY type '{name}'")))
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    scalar: Scalar,
+    // Type of the item count for list properties.
+    list: Option<Scalar>
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+// Reads scalars from the body, either whitespace separated text or packed binary.
+struct BodyReader<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    offset: usize
+}
+
+impl BodyReader<'_> {
+    fn read(&mut self, scalar: Scalar) -> io::Result<f64> {
+        if self.format == Format::Ascii {
+            let start = self.offset + self.bytes[self.offset..].iter().take_while(|b| b.is_ascii_whitespace()).count();
+            let end = start + self.bytes[start..].iter().take_while(|b| !b.is_ascii_whitespace()).count();
+            let token = std::str::from_utf8(&self.bytes[start..end]).unwrap_or_default();
+
+            self.offset = end;
+            return token.parse().map_err(|_| invalid_data(format!("Invalid PLY value '{token}'")));
+        }
+
+        let bytes = self.bytes.get(self.offset..self.offset + scalar.size())
+            .ok_or_else(|| invalid_data("Unexpected end of PLY file".to_owned()))?;
+        let mut buf = [0u8; 8];
+
+        buf[..bytes.len()].copy_from_slice(bytes);
+
+        if self.format == Format::BinaryBigEndian {
+            buf[..bytes.len()].reverse();
+        }
+
+        self.offset += scalar.size();
+
+        Ok(match scalar {
+            Scalar::I8 => buf[0] as i8 as f64,
+            Scalar::U8 => buf[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(buf)
+        })
+    }
+}
+
+// Loads an ASCII or binary PLY file with positions and optionally normals, texture coordinates and
+// vertex colors. Polygons are triangulated as fans, normals are computed when the file has none.
+pub fn load_ply<P: AsRef<Path>>(path: P) -> io::Result<Mesh> {
+    let name = path.as_ref().file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut mesh = parse_ply(&fs::read(path)?)?;
+
+    mesh.name = name;
+    Ok(mesh)
+}
+
+pub fn parse_ply(bytes: &[u8]) -> io::Result<Mesh> {
+    let header_end = bytes.windows(10).position(|w| w == b"end_header")
+        .ok_or_else(|| invalid_data("PLY header has no end_header".to_owned()))?;
+    let header = std::str::from_utf8(&bytes[..header_end]).map_err(|e| invalid_data(format!("PLY header is not valid UTF-8: {e}")))?;
+
+    let mut lines = header.lines().map(str::trim);
+
+    if lines.next() != Some("ply") {
+        return Err(invalid_data("Not a PLY file".to_owned()));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    for line in lines {
+        let words = line.split_ascii_whitespace().collect::<Vec<_>>();
+
+        match words[..] {
+            ["format", "ascii", _] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+            ["format", "binary_big_endian", _] => format = Some(Format::BinaryBigEndian),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_owned(),
+                count: count.parse().map_err(|_| invalid_data(format!("Invalid PLY element count '{count}'")))?,
+                properties: Vec::new()
+            }),
+            ["property", "list", count, item, name] => elements.last_mut()
+                .ok_or_else(|| invalid_data("PLY property before any element".to_owned()))?
+                .properties.push(Property { name: name.to_owned(), scalar: Scalar::parse(item)?, list: Some(Scalar::parse(count)?) }),
+            ["property", scalar, name] => elements.last_mut()
+                .ok_or_else(|| invalid_data("PLY property before any element".to_owned()))?
+                .properties.push(Property { name: name.to_owned(), scalar: Scalar::parse(scalar)?, list: None }),
+            _ => ()
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("PLY header has no format".to_owned()))?;
+
+    // The body starts after the line ending of end_header.
+    let body_start = header_end + bytes[header_end..].iter().position(|b| *b == b'\n').map_or(bytes.len() - header_end, |i| i + 1);
+    let mut reader = BodyReader { format, bytes: &bytes[body_start..], offset: 0 };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut texcoords = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut values = Vec::with_capacity(element.properties.len());
+            let mut polygon = Vec::new();
+
+            for property in &element.properties {
+                match property.list {
+                    Some(count_scalar) => {
+                        let count = reader.read(count_scalar)? as usize;
+                        let items = (0..count).map(|_| reader.read(property.scalar)).collect::<io::Result<Vec<_>>>()?;
+
+                        if property.name == "vertex_indices" || property.name == "vertex_index" {
+                            polygon = items;
+                        }
+                    },
+                    None => values.push((property.name.as_str(), property.scalar, reader.read(property.scalar)?))
+                }
+            }
+
+            let get = |names: &[&str]| values.iter().find(|(name, _, _)| names.contains(name)).map(|(_, scalar, value)| (*scalar, *value));
+
+            match element.name.as_str() {
+                "vertex" => {
+                    for axis in ["x", "y", "z"] {
+                        positions.push(get(&[axis]).ok_or_else(|| invalid_data(format!("PLY vertex has no {axis}")))?.1 as f32);
+                    }
+
+                    if let (Some(nx), Some(ny), Some(nz)) = (get(&["nx"]), get(&["ny"]), get(&["nz"])) {
+                        normals.extend([nx.1 as f32, ny.1 as f32, nz.1 as f32]);
+                    }
+
+                    if let (Some(u), Some(v)) = (get(&["u", "s", "texture_u"]), get(&["v", "t", "texture_v"])) {
+                        texcoords.extend([u.1 as f32, v.1 as f32]);
+                    }
+
+                    if let (Some(r), Some(g), Some(b)) = (get(&["red", "r"]), get(&["green", "g"]), get(&["blue", "b"])) {
+                        // Integer colors are 0 to 255, float colors 0 to 1, both in sRGB.
+                        colors.extend([r, g, b].map(|(scalar, value)| match scalar {
+                            Scalar::F32 | Scalar::F64 => srgb_to_linear((value.clamp(0.0, 1.0) * 255.0).round() as u8),
+                            _ => srgb_to_linear(value.clamp(0.0, 255.0) as u8)
+                        }));
+                    }
+                },
+                "face" => {
+                    for i in 1..polygon.len().saturating_sub(1) {
+                        indices.extend([polygon[0] as u32, polygon[i] as u32, polygon[i + 1] as u32]);
+                    }
+                },
+                _ => ()
+            }
+        }
+    }
+
+    let count = positions.len() / 3;
+
+    if let Some(index) = indices.iter().find(|i| **i as usize >= count) {
+        return Err(invalid_data(format!("PLY face refers to vertex {index}, but there are only {count}")));
+    }
+
+    let mut mesh = Mesh::new(String::new(), positions, normals, texcoords, indices);
+
+    if colors.len() == count * 3 {
+        mesh.colors = colors;
+    }
+
+    mesh.generate_missing(NormalMode::Smooth);
+    mesh.validate().map_err(|e| invalid_data(e.to_string()))?;
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = "0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n2 2 0 0 0 0\n4 0 1 2 3\n3 1 4 2\n";
+    const HEADER: &str = "ply\nformat ascii 1.0\nelement vertex 5\nproperty float x\nproperty float y\nproperty float z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\nelement face 2\nproperty list uchar int vertex_indices\nend_header\n";
+
+    #[test]
+    fn ascii_with_colors_and_polygons() {
+        let mesh = parse_ply(format!("{HEADER}{BODY}").as_bytes()).unwrap();
+
+        assert_eq!(mesh.positions.len(), 15);
+        // The quad becomes a fan of two triangles.
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 1, 4, 2]);
+        assert_eq!(&mesh.colors[..6], &[1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(mesh.normals.len(), 15);
+        assert!(mesh.validate().is_ok());
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        let mut bytes = b"ply\nformat binary_big_endian 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar ushort vertex_index\nend_header\n".to_vec();
+
+        for position in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            bytes.extend(position.iter().flat_map(|f| f.to_be_bytes()));
+        }
+
+        bytes.push(3);
+        bytes.extend([0u16, 1, 2].iter().flat_map(|i| i.to_be_bytes()));
+
+        let mesh = parse_ply(&bytes).unwrap();
+
+        assert_eq!(mesh.positions, [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert!(mesh.colors.is_empty());
+    }
+
+    #[test]
+    fn unsupported_format_and_properties() {
+        let unknown_format = HEADER.replace("format ascii 1.0", "format binary_middle_endian 1.0");
+        assert!(parse_ply(unknown_format.as_bytes()).is_err());
+
+        let unknown_type = HEADER.replace("property float x", "property int128 x");
+        assert!(parse_ply(unknown_type.as_bytes()).is_err());
+
+        let no_x = HEADER.replace("property float x", "property float w");
+        assert!(parse_ply(format!("{no_x}{BODY}").as_bytes()).is_err());
+
+        assert!(parse_ply(b"ply\nformat ascii 1.0\nelement vertex 0\n").is_err());
+        assert!(parse_ply(b"solid\nend_header\n").is_err());
+    }
+
+    #[test]
+    fn faces_out_of_range() {
+        let header = HEADER.replace("element vertex 5", "element vertex 3").replace("element face 2", "element face 1");
+        let body = "0 0 0 0 0 0\n1 0 0 0 0 0\n0 1 0 0 0 0\n3 0 1 7\n";
+
+        assert!(parse_ply(format!("{header}{body}").as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use glm::{vec3, Vec3};
+
+use crate::{camera::Camera, char::CharColor, vertex::lerp};
+
+const LUMA: Vec3 = Vec3::new(0.2126, 0.7152, 0.0722);
+
+// A single post-processing pass. Colors are floats in [0, 1], distances are in view space units.
+#[derive(Debug, Clone)]
+pub enum PostPass {
+    // Blends towards `color` between the `start` and `end` distance from the camera.
+    Fog { color: Vec3, start: f32, end: f32 },
+    // Darkens pixels towards the corners, beginning at `radius` (0 = center, 1 = corner).
+    Vignette { radius: f32, strength: f32 },
+    Gamma(f32),
+    ColorGrade { exposure: f32, contrast: f32, saturation: f32, tint: Vec3 },
+    // Blurs everything brighter than `threshold` over `radius` pixels and adds it back on top.
+    Bloom { threshold: f32, radius: usize, intensity: f32 },
+    // Draws `color` at depth discontinuities (silhouettes) and at creases where the surface
+    // orientation changes. Framebuffers don't keep per-pixel normals, so creases are found from
+    // the second derivative of depth instead of normal discontinuities and faint ones are missed.
+    Outline { color: Vec3, depth_threshold: f32, crease_threshold: f32 }
+}
+
+// Ordered list of passes applied between rasterization and cell encoding.
+#[derive(Debug, Clone)]
+pub struct PostChain {
+    pub passes: Vec<PostPass>,
+    // Near and far planes of the projection, used to turn the depth buffer into view space distances.
+    near: f32,
+    far: f32
+}
+
+impl Default for PostChain {
+    fn default() -> Self {
+        Self::new(Vec::new(), &Camera::default())
+    }
+}
+
+impl PostChain {
+    // Distances are measured with the projection of `camera`.
+    pub fn new(passes: Vec<PostPass>, camera: &Camera) -> Self {
+        Self { passes, near: camera.near, far: camera.far }
+    }
+
+    // Takes the depth range of the camera the next frame is drawn with.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.near = camera.near;
+        self.far = camera.far;
+    }
+
+    pub fn apply(&self, pixels: &mut [Option<CharColor>], depth: &[f32], w: usize, h: usize) {
+        if self.passes.is_empty() {
+            return;
+        }
+
+        let mut colors = pixels.iter()
+            .map(|p| p.map(|c| vec3(c.r as f32, c.g as f32, c.b as f32) / 255.0))
+            .collect::<Vec<_>>();
+        let distances = depth.iter().map(|z| self.linearize_depth(*z)).collect::<Vec<_>>();
+
+        for pass in &self.passes {
+            pass.apply(&mut colors, &distances, w, h);
+        }
+
+        for (pixel, color) in pixels.iter_mut().zip(colors) {
+            *pixel = color.map(|c| {
+                let c = c.map(|v| (v.clamp(0.0, 1.0) * 255.0).round());
+                CharColor { r: c.x as u8, g: c.y as u8, b: c.z as u8 }
+            });
+        }
+    }
+
+    // NDC depth in [-1, 1] -> distance from the camera along the view direction.
+    fn linearize_depth(&self, z: f32) -> f32 {
+        if !z.is_finite() {
+            return f32::INFINITY;
+        }
+
+        2.0 * self.far * self.near / ((self.far + self.near) - z * (self.far - self.near))
+    }
+}
+
+impl PostPass {
+    pub fn apply(&self, colors: &mut [Option<Vec3>], distances: &[f32], w: usize, h: usize) {
+        match *self {
+            PostPass::Fog { color, start, end } => {
+                // Lines and the background don't write depth, so they stay unfogged.
+                for (c, d) in colors.iter_mut().zip(distances) {
+                    if !d.is_finite() {
+                        continue;
+                    }
+
+                    if let Some(c) = c {
+                        let amount = ((d - start) / (end - start)).clamp(0.0, 1.0);
+                        *c = c.lerp(&color, amount);
+                    }
+                }
+            },
+            PostPass::Vignette { radius, strength } => {
+                for y in 0..h {
+                    for x in 0..w {
+                        if let Some(c) = &mut colors[y * w + x] {
+                            let dx = (x as f32 + 0.5) / w as f32 - 0.5;
+                            let dy = (y as f32 + 0.5) / h as f32 - 0.5;
+                            let dist = (dx * dx + dy * dy).sqrt() * std::f32::consts::SQRT_2;
+                            let t = ((dist - radius) / (1.0 - radius).max(f32::EPSILON)).clamp(0.0, 1.0);
+
+                            *c *= 1.0 - strength * t * t * (3.0 - 2.0 * t);
+                        }
+                    }
+                }
+            },
+            PostPass::Gamma(gamma) => {
+                for c in colors.iter_mut().flatten() {
+                    *c = c.map(|v| v.max(0.0).powf(1.0 / gamma));
+                }
+            },
+            PostPass::ColorGrade { exposure, contrast, saturation, tint } => {
+                for c in colors.iter_mut().flatten() {
+                    let graded = (*c * exposure).add_scalar(-0.5) * contrast;
+                    let graded = graded.add_scalar(0.5);
+                    let luma = graded.dot(&LUMA);
+
+                    *c = graded.map(|v| lerp(luma, v, saturation)).component_mul(&tint);
+                }
+            },
+            PostPass::Bloom { threshold, radius, intensity } => bloom(colors, w, h, threshold, radius, intensity),
+            PostPass::Outline { color, depth_threshold, crease_threshold } =>
+                outline(colors, distances, w, h, color, depth_threshold, crease_threshold)
+        }
+    }
+}
+
+fn bloom(colors: &mut [Option<Vec3>], w: usize, h: usize, threshold: f32, radius: usize, intensity: f32) {
+    let bright = colors.iter()
+        .map(|c| c.map_or(Vec3::zeros(), |c| (c - Vec3::repeat(threshold)).map(|v| v.max(0.0))))
+        .collect::<Vec<_>>();
+
+    // Separable box blur
+    let horizontal = blur(&bright, w, h, radius, (1, 0));
+    let glow = blur(&horizontal, w, h, radius, (0, 1));
+
+    for (c, glow) in colors.iter_mut().zip(glow) {
+        let glow = glow * intensity;
+
+        match c {
+            Some(c) => *c += glow,
+            // Let the glow bleed onto the terminal background once it's visible.
+            None if glow.dot(&LUMA) > 0.02 => *c = Some(glow),
+            None => ()
+        }
+    }
+}
+
+fn blur(src: &[Vec3], w: usize, h: usize, radius: usize, (dx, dy): (usize, usize)) -> Vec<Vec3> {
+    let mut dst = vec![Vec3::zeros(); src.len()];
+    let r = radius as isize;
+
+    for y in 0..h {
+        for x in 0..w {
+            let mut sum = Vec3::zeros();
+
+            for i in -r..=r {
+                let sx = x as isize + i * dx as isize;
+                let sy = y as isize + i * dy as isize;
+
+                if sx >= 0 && sy >= 0 && (sx as usize) < w && (sy as usize) < h {
+                    sum += src[sy as usize * w + sx as usize];
+                }
+            }
+
+            dst[y * w + x] = sum / (2 * radius + 1) as f32;
+        }
+    }
+
+    dst
+}
+
+fn outline(colors: &mut [Option<Vec3>], distances: &[f32], w: usize, h: usize,
+    color: Vec3, depth_threshold: f32, crease_threshold: f32) {
+    let depth_at = |x: isize, y: isize| {
+        if x < 0 || y < 0 || x as usize >= w || y as usize >= h {
+            return f32::INFINITY;
+        }
+
+        distances[y as usize * w + x as usize]
+    };
+
+    let mut edges = vec![false; w * h];
+
+    for y in 0..h as isize {
+        for x in 0..w as isize {
+            let d = depth_at(x, y);
+            let neighbours = [depth_at(x - 1, y), depth_at(x + 1, y), depth_at(x, y - 1), depth_at(x, y + 1)];
+
+            // Silhouettes and overlaps: a neighbour is significantly closer, so the line lands on the far side.
+            let discontinuity = neighbours.iter().any(|n| n.is_finite() && (d - n) > depth_threshold * n);
+
+            // Creases: depth is continuous but its slope changes.
+            let crease = d.is_finite() && neighbours.iter().all(|n| n.is_finite()) &&
+                ((neighbours[0] + neighbours[1] - 2.0 * d).abs() / d > crease_threshold ||
+                 (neighbours[2] + neighbours[3] - 2.0 * d).abs() / d > crease_threshold);
+
+            edges[y as usize * w + x as usize] = discontinuity || crease;
+        }
+    }
+
+    for (c, edge) in colors.iter_mut().zip(edges) {
+        if edge {
+            *c = Some(color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glm::vec4;
+
+    use super::*;
+
+    #[test]
+    fn distances_use_the_camera_depth_range() {
+        let camera = Camera { near: 0.01, far: 50.0, ..Camera::default() };
+        let chain = PostChain::new(Vec::new(), &camera);
+
+        for distance in [0.5, 4.0, 20.0] {
+            let clip = camera.proj_matrix(1.0) * vec4(0.0, 0.0, -distance, 1.0);
+            assert!((chain.linearize_depth(clip.z / clip.w) - distance).abs() < distance * 1e-3, "distance {distance}");
+        }
+    }
+
+    #[test]
+    fn fog_skips_pixels_without_depth() {
+        let fog = PostPass::Fog { color: Vec3::repeat(1.0), start: 0.0, end: 10.0 };
+        let mut colors = vec![Some(Vec3::zeros()); 3];
+
+        fog.apply(&mut colors, &[5.0, f32::INFINITY, 20.0], 3, 1);
+
+        assert_eq!(colors, [Some(Vec3::repeat(0.5)), Some(Vec3::zeros()), Some(Vec3::repeat(1.0))]);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{io::{self, Write}, path::Path, time::Duration};
+
+use glm::{make_vec3, rotate, translate, vec3, Mat4, Vec3};
+
+use crate::{camera::Camera, color::linear_to_srgb, error::EngineError, mesh::Mesh, char::{AnsiColorMode, CharColor, ANSI_CLEAR_SCREEN, ANSI_HIDE_CURSOR, ANSI_MOVE_CURSOR_TO_START, ANSI_SHOW_CURSOR}, ply::load_ply, raster::{CellMode, Framebuf}, scene::Scene, stl::load_stl, vertex::Vertex};
+
+pub static USAGE: &str = "Usage: render <obj|gltf|glb|stl|ply> [--camera x,y,z] [--target x,y,z] [--fov degrees] [--cell-aspect ratio] [--size WxH] \
+[--mode half|braille|ascii] [--color truecolor|256|none] [--samples N] [--turntable frames] [--frame-time ms] [--out file.png|file.ppm]";
+
+// Lambert shading with a fixed light tinted by the vertex color, enough to judge the shape of an asset.
+pub fn preview_shader(vertex: &Vertex) -> CharColor {
+    let normal = make_vec3(&vertex.attributes[2..5]).normalize();
+    let diffuse = normal.dot(&vec3(0.5, -0.7, 0.5).normalize()).max(0.0);
+    let value = 40.0 + diffuse * 215.0;
+    let tint = |c: f32| (value * linear_to_srgb(c) as f32 / 255.0) as u8;
+
+    CharColor { r: tint(vertex.attributes[5]), g: tint(vertex.attributes[6]), b: tint(vertex.attributes[7]) }
+}
+
+// Center and radius of a sphere around all vertices of the models.
+pub fn bounding_sphere(meshes: &[Mesh]) -> (Vec3, f32) {
+    let positions = meshes.iter()
+        .flat_map(|m| m.positions.chunks(3).map(make_vec3))
+        .collect::<Vec<Vec3>>();
+    let center = positions.iter().sum::<Vec3>() / positions.len().max(1) as f32;
+    let radius = positions.iter().map(|p| (p - center).norm()).fold(0.0, f32::max).max(f32::EPSILON);
+
+    (center, radius)
+}
+
+// Loads a model by file extension, other formats become a scene with one node per mesh.
+pub fn load_scene(path: &str) -> Result<Scene, EngineError> {
+    let extension = Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+    let scene: Result<_, EngineError> = match extension.as_str() {
+        "gltf" | "glb" => Scene::load_gltf(path),
+        "stl" => load_stl(path).map(|mesh| Scene::from_meshes(vec![mesh])).map_err(Into::into),
+        "ply" => load_ply(path).map(|mesh| Scene::from_meshes(vec![mesh])).map_err(Into::into),
+        _ => Mesh::load_obj(path).map(Scene::from_meshes).map_err(Into::into)
+    };
+
+    scene.map_err(|e| EngineError::asset(path, e))
+}
+
+struct PreviewOptions {
+    path: String,
+    camera: Option<Vec3>,
+    target: Option<Vec3>,
+    fov: Option<f32>,
+    cell_aspect: Option<f32>,
+    size: (usize, usize),
+    cell_mode: CellMode,
+    color_mode: AnsiColorMode,
+    samples: usize,
+    turntable: Option<usize>,
+    frame_time: u64,
+    // Writes the frame's pixels to an image instead of printing it.
+    out: Option<String>
+}
+
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let components = value.split(',').map(|c| c.trim().parse::<f32>()).collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid vector '{value}': {e}"))?;
+
+    match components[..] {
+        [x, y, z] => Ok(vec3(x, y, z)),
+        _ => Err(format!("Invalid vector '{value}', expected x,y,z"))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value '{value}' for {name}"))
+}
+
+fn parse_options(args: &[String]) -> Result<PreviewOptions, String> {
+    let mut options = PreviewOptions {
+        path: String::new(),
+        camera: None,
+        target: None,
+        fov: None,
+        cell_aspect: None,
+        size: (48, 48),
+        cell_mode: CellMode::HalfBlock,
+        color_mode: AnsiColorMode::AnsiTrueColor,
+        samples: 1,
+        turntable: None,
+        frame_time: 50,
+        out: None
+    };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            options.path = arg.clone();
+            continue;
+        }
+
+        let value = args.next().ok_or(format!("Missing value for {arg}"))?;
+
+        match arg.as_str() {
+            "--camera" => options.camera = Some(parse_vec3(value)?),
+            "--target" => options.target = Some(parse_vec3(value)?),
+            "--fov" => options.fov = Some(parse_number::<f32>(arg, value)?.to_radians()),
+            "--cell-aspect" => options.cell_aspect = Some(parse_number(arg, value)?),
+            "--size" => options.size = match value.split_once('x') {
+                Some((w, h)) => (parse_number(arg, w)?, parse_number(arg, h)?),
+                None => return Err(format!("Invalid size '{value}', expected WxH"))
+            },
+            "--mode" => options.cell_mode = match value.as_str() {
+                "half" => CellMode::HalfBlock,
+                "braille" => CellMode::Braille,
+                "ascii" => CellMode::Ascii,
+                _ => return Err(format!("Unknown mode '{value}'"))
+            },
+            "--color" => options.color_mode = match value.as_str() {
+                "truecolor" => AnsiColorMode::AnsiTrueColor,
+                "256" => AnsiColorMode::Ansi256,
+                "none" => AnsiColorMode::NoColor,
+                _ => return Err(format!("Unknown color mode '{value}'"))
+            },
+            "--samples" => options.samples = parse_number(arg, value)?,
+            "--turntable" => options.turntable = Some(parse_number(arg, value)?),
+            "--frame-time" => options.frame_time = parse_number(arg, value)?,
+            "--out" => options.out = Some(value.clone()),
+            _ => return Err(format!("Unknown option {arg}"))
+        }
+    }
+
+    if options.path.is_empty() {
+        return Err("Missing model path".to_owned());
+    }
+
+    let (w, h) = options.size;
+    let (cell_w, cell_h) = options.cell_mode.cell_size();
+
+    if w == 0 || h == 0 {
+        return Err(format!("Invalid size {w}x{h}, width and height must be at least 1"));
+    }
+
+    // Columns and rows that don't fill a whole cell would be dropped.
+    if w % cell_w != 0 {
+        return Err(format!("Invalid size {w}x{h}, the width must be a multiple of {cell_w} in this mode"));
+    }
+
+    if h % cell_h != 0 {
+        return Err(format!("Invalid size {w}x{h}, the height must be a multiple of {cell_h} in this mode"));
+    }
+
+    if let Some(out) = &options.out {
+        if options.turntable.is_some() {
+            return Err("--out writes a single frame and can't be combined with --turntable".to_owned());
+        }
+
+        if image_format(out).is_none() {
+            return Err(format!("Unsupported image '{out}', expected a .png or .ppm file"));
+        }
+    }
+
+    Ok(options)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImageFormat {
+    Png,
+    Ppm
+}
+
+fn image_format(path: &str) -> Option<ImageFormat> {
+    match Path::new(path).extension()?.to_string_lossy().to_lowercase().as_str() {
+        "png" => Some(ImageFormat::Png),
+        "ppm" => Some(ImageFormat::Ppm),
+        _ => None
+    }
+}
+
+// Renders a model file to stdout without starting the server, either a single frame or one turntable revolution.
+pub fn run(args: &[String]) -> Result<(), EngineError> {
+    let options = parse_options(args)?;
+
+    let scene = load_scene(&options.path)?;
+    let pose = scene.rest_pose();
+    let (center, radius) = bounding_sphere(&scene.baked_meshes());
+
+    let mut camera = Camera::framing(&center, radius, &vec3(1.0, -1.0, 2.0));
+    camera.fov = options.fov.unwrap_or(camera.fov);
+    camera.cell_aspect = options.cell_aspect.unwrap_or(camera.cell_aspect);
+    camera.target = options.target.unwrap_or(center);
+
+    if let Some(position) = options.camera {
+        camera.position = position;
+        camera.far = camera.far.max((position - center).norm() + radius * 2.0);
+    }
+
+    let mut fb = Framebuf::new(options.size.0, options.size.1);
+    fb.set_samples(options.samples);
+    fb.cell_mode = options.cell_mode;
+    fb.post.set_camera(&camera);
+
+    let vp_matrix = camera.fb_proj_matrix(&fb) * camera.view_matrix();
+    let frames = options.turntable.unwrap_or(1).max(1);
+    let mut stdout = io::stdout();
+
+    for i in 0..frames {
+        // Spin around the vertical axis through the model's center.
+        let angle = i as f32 / frames as f32 * std::f32::consts::TAU;
+        let model_matrix = translate(&rotate(&translate(&Mat4::identity(), &center), angle, &vec3(0.0, 1.0, 0.0)), &-center);
+
+        fb.clear();
+        scene.draw_pose(&mut fb, &pose, &model_matrix, &vp_matrix, &camera.position, &preview_shader);
+
+        fb.resolve();
+
+        if let Some(out) = &options.out {
+            let image = fb.pixel_image(CharColor { r: 0, g: 0, b: 0 });
+
+            match image_format(out) {
+                Some(ImageFormat::Ppm) => image.write_ppm(out)?,
+                _ => image.write_png(out)?
+            }
+
+            println!("Wrote {}x{} pixels to {out}", fb.w, fb.h);
+            return Ok(());
+        }
+
+        let mut str = String::new();
+
+        if options.turntable.is_some() {
+            if i == 0 {
+                str.push_str(ANSI_CLEAR_SCREEN);
+            }
+
+            str.push_str(ANSI_HIDE_CURSOR);
+            str.push_str(ANSI_MOVE_CURSOR_TO_START);
+        }
+
+        str.push_str(&fb.to_string(&options.color_mode));
+        stdout.write_all(str.as_bytes())?;
+        stdout.flush()?;
+
+        if options.turntable.is_some() {
+            std::thread::sleep(Duration::from_millis(options.frame_time));
+        }
+    }
+
+    if options.turntable.is_some() {
+        print!("{ANSI_SHOW_CURSOR}");
+    }
+
+    Ok(())
+}
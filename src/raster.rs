@@ -1,39 +1,128 @@
 use core::f32;
 use std::iter::zip;
 
-use glm::{make_vec4, vec2, vec3, vec4, Mat3, Mat4, Vec2, Vec3, Vec4};
+use glm::{make_vec3, make_vec4, mat4_to_mat3, vec2, vec3, vec4, Mat3, Mat4, Vec2, Vec3, Vec4};
 use itertools::{izip, Itertools};
-use tobj::Model;
 
-use crate::{char::{AnsiColorMode, CharColor, CharInfo}, clip::{clip_triangle, should_backface_cull}, vertex::Vertex};
+use crate::{anim::skin_mesh, background::{view_direction, Background}, char::{AnsiColorMode, CharColor, CharInfo}, clip::{clip_triangle, should_backface_cull}, color::LinearColor, frustum::Frustum, mesh::Mesh, post::PostChain, stencil::StencilState, texture::Texture, vertex::Vertex};
 
-pub fn half_block_shader(c: &mut CharInfo, half: &CharHalf, color: &CharColor) {
-    match (half, c.char_code) {
-        (CharHalf::Top, '▄') => c.bg_color = Some(color.clone()),
-        (CharHalf::Bottom, '▀') => c.bg_color = Some(color.clone()),
-        (CharHalf::Top, _) => {
-            c.char_code = '▀';
-            c.fg_color = Some(color.clone());
-        },
-        (CharHalf::Bottom, _) => {
-            c.char_code = '▄';
-            c.fg_color = Some(color.clone());
+// Borrowed so shaders can capture state, e.g. the texture of an offscreen target they sample.
+pub type Shader<'a> = &'a dyn Fn(&Vertex) -> CharColor;
+
+// Transforms normals by `model_matrix` so they stay perpendicular under non-uniform scaling.
+// Degenerate matrices fall back to the identity.
+pub fn normal_matrix(model_matrix: &Mat4) -> Mat3 {
+    mat4_to_mat3(&model_matrix.try_inverse().unwrap_or_else(Mat4::identity).transpose())
+}
+
+// Per-instance data for `Framebuf::draw_instanced`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instance {
+    pub model_matrix: Mat4,
+    // Multiplied with the vertex colors.
+    pub tint: LinearColor,
+    // Passed to shaders as the last attribute, e.g. to offset animations so instances don't move in sync.
+    pub phase: f32
+}
+
+impl Instance {
+    pub fn new(model_matrix: Mat4) -> Self {
+        Self { model_matrix, tint: LinearColor::WHITE, phase: 0.0 }
+    }
+}
+
+// Rectangle in pixels, used for viewports and scissor rectangles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, w: usize, h: usize) -> Self {
+        Self { x, y, w, h }
+    }
+
+    // Overlap of both rectangles, empty rectangles have zero size.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let right = (self.x + self.w).min(other.x + other.w).max(x);
+        let bottom = (self.y + self.h).min(other.y + other.h).max(y);
+
+        Rect::new(x, y, right - x, bottom - y)
+    }
+}
+
+// How resolved pixels are encoded into terminal cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellMode {
+    // 1x2 pixels per cell using '▀' and '▄' with foreground and background colors.
+    HalfBlock,
+    // 2x4 pixels per cell using Braille dots with a single foreground color.
+    Braille,
+    // 1x2 pixels per cell using characters of increasing density for brightness.
+    Ascii
+}
+
+impl CellMode {
+    // Number of pixels covered by one cell.
+    pub fn cell_size(self) -> (usize, usize) {
+        match self {
+            CellMode::HalfBlock | CellMode::Ascii => (1, 2),
+            CellMode::Braille => (2, 4)
         }
     }
 }
 
-pub type Shader = fn(&Vertex, &mut CharInfo, &CharHalf);
+// Reconstruction filter used when resolving supersampled samples into pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolveFilter {
+    // Averages the samples inside the pixel.
+    Box,
+    // Weights samples by distance to the pixel center, reaching half way into neighbouring pixels.
+    Tent
+}
+
+impl ResolveFilter {
+    fn radius(self, samples: f32) -> f32 {
+        match self {
+            ResolveFilter::Box => samples / 2.0,
+            ResolveFilter::Tent => samples
+        }
+    }
 
-pub enum CharHalf {
-    Top,
-    Bottom
+    fn weight(self, distance: f32, samples: f32) -> f32 {
+        match self {
+            ResolveFilter::Box => if distance < samples / 2.0 { 1.0 } else { 0.0 },
+            ResolveFilter::Tent => (1.0 - distance / samples).max(0.0)
+        }
+    }
 }
 
 pub struct Framebuf {
+    // Size in pixels. Color, depth and stencil are stored per sample, `samples` x `samples` per pixel.
     pub w: usize,
     pub h: usize,
+    pub samples: usize,
+    pub color_buf: Vec<Option<CharColor>>,
+    pub z_buf: Vec<f32>,
+    pub stencil_buf: Vec<u8>,
+    // Pixels and their nearest depth produced by `resolve`, before they are encoded into cells.
+    pub pixel_buf: Vec<Option<CharColor>>,
+    pub pixel_z_buf: Vec<f32>,
     pub char_buf: Vec<CharInfo>,
-    pub z_buf: Vec<f32>
+    pub cell_mode: CellMode,
+    pub filter: ResolveFilter,
+    // Post-processing passes applied to the resolved pixels.
+    pub post: PostChain,
+    // Stencil test and operations used by subsequent draws. None disables stencil testing.
+    pub stencil: Option<StencilState>,
+    // Sub-rectangle NDC is mapped to, e.g. one half for split-screen. None uses the whole framebuffer.
+    pub viewport: Option<Rect>,
+    // Draws, backgrounds and clears only touch pixels inside this rectangle. None disables the scissor test.
+    pub scissor: Option<Rect>
 }
 
 impl Framebuf {
@@ -41,22 +130,327 @@ impl Framebuf {
         Self {
             w,
             h,
+            samples: 1,
+            color_buf: vec![None; w * h],
+            z_buf: vec![0.0; w * h],
+            stencil_buf: vec![0; w * h],
+            pixel_buf: vec![None; w * h],
+            pixel_z_buf: vec![f32::INFINITY; w * h],
             char_buf: vec![Default::default(); w * (h / 2)],
-            z_buf: vec![0.0; w * h]
+            cell_mode: CellMode::HalfBlock,
+            filter: ResolveFilter::Box,
+            post: PostChain::default(),
+            stencil: None,
+            viewport: None,
+            scissor: None
         }
     }
 
+    // Sets the supersampling factor per axis (1 disables anti-aliasing). Reallocates the sample buffers.
+    pub fn set_samples(&mut self, samples: usize) {
+        let samples = samples.max(1);
+        let len = self.w * samples * self.h * samples;
+
+        self.samples = samples;
+        self.color_buf = vec![None; len];
+        self.z_buf = vec![f32::INFINITY; len];
+        self.stencil_buf = vec![0; len];
+    }
+
+    // Reallocates every buffer for a new size in pixels, keeping samples, cell mode and post-processing.
+    pub fn resize(&mut self, w: usize, h: usize) {
+        self.w = w;
+        self.h = h;
+        self.pixel_buf = vec![None; w * h];
+        self.pixel_z_buf = vec![f32::INFINITY; w * h];
+        self.char_buf = vec![Default::default(); w * (h / 2)];
+        self.set_samples(self.samples);
+    }
+
+    // Sizes the framebuffer to exactly fill `cols` x `rows` terminal cells in the current cell mode.
+    pub fn resize_cells(&mut self, cols: usize, rows: usize) {
+        let (cw, ch) = self.cell_mode.cell_size();
+        self.resize(cols * cw, rows * ch);
+    }
+
+    // Width and height of the sample buffers.
+    pub fn sample_w(&self) -> usize {
+        self.w * self.samples
+    }
+
+    pub fn sample_h(&self) -> usize {
+        self.h * self.samples
+    }
+
+    // Number of cell columns and rows for the current cell mode.
+    pub fn cols(&self) -> usize {
+        self.w / self.cell_mode.cell_size().0
+    }
+
+    pub fn rows(&self) -> usize {
+        self.h / self.cell_mode.cell_size().1
+    }
+
+    // The viewport in pixels, the whole framebuffer if none is set.
+    pub fn viewport_rect(&self) -> Rect {
+        self.viewport.unwrap_or(Rect::new(0, 0, self.w, self.h))
+    }
+
+    // Samples that may be written, the viewport limited by the scissor rectangle and the buffer.
+    fn sample_bounds(&self) -> Rect {
+        let n = self.samples;
+        let full = Rect::new(0, 0, self.w, self.h);
+        let bounds = self.viewport_rect().intersect(&self.scissor.unwrap_or(full)).intersect(&full);
+
+        Rect::new(bounds.x * n, bounds.y * n, bounds.w * n, bounds.h * n)
+    }
+
     pub fn clear(&mut self) {
-        self.char_buf.fill(Default::default());
-        self.z_buf.fill(f32::INFINITY);
+        let Some(scissor) = self.scissor else {
+            self.color_buf.fill(None);
+            self.z_buf.fill(f32::INFINITY);
+            self.stencil_buf.fill(0);
+            return;
+        };
+
+        let n = self.samples;
+        let scissor = scissor.intersect(&Rect::new(0, 0, self.w, self.h));
+        let sw = self.sample_w();
+
+        for y in scissor.y * n..(scissor.y + scissor.h) * n {
+            let row = y * sw + scissor.x * n..y * sw + (scissor.x + scissor.w) * n;
+
+            self.color_buf[row.clone()].fill(None);
+            self.z_buf[row.clone()].fill(f32::INFINITY);
+            self.stencil_buf[row].fill(0);
+        }
     }
 
-    pub fn to_string(&self, mode: &AnsiColorMode) -> String {
-        let mut str = String::with_capacity(self.w * self.h / 2 * 45 + self.h / 2);
+    // Fills every sample the geometry didn't touch. Call after drawing, `vp_matrix` is only used by skyboxes.
+    pub fn draw_background(&mut self, background: &Background, vp_matrix: &Mat4) {
+        if let Background::None = background {
+            return;
+        }
+
+        let inv_vp_matrix = vp_matrix.try_inverse().unwrap_or_else(Mat4::identity);
+        let sw = self.sample_w();
+        let bounds = self.sample_bounds();
+        let (vx, vy, vw, vh) = self.sample_viewport();
+
+        for y in bounds.y..bounds.y + bounds.h {
+            for x in bounds.x..bounds.x + bounds.w {
+                let index = y * sw + x;
 
-        for y in 0..self.h / 2 {
+                if self.z_buf[index] != f32::INFINITY || self.color_buf[index].is_some() {
+                    continue;
+                }
+
+                // Inverse of the viewport transformation in `prepare_position`.
+                let ndc_x = (x as f32 - vx + 0.5) / (vw - 1.0).max(1.0) * 2.0 - 1.0;
+                let ndc_y = (y as f32 - vy + 0.5) / (vh - 1.0).max(1.0) * 2.0 - 1.0;
+
+                self.color_buf[index] = background.color(
+                    (y as f32 - vy + 0.5) / vh, 
+                    || view_direction(&inv_vp_matrix, ndc_x, ndc_y));
+            }
+        }
+    }
+
+    pub fn clear_stencil(&mut self, value: u8) {
+        self.stencil_buf.fill(value);
+    }
+
+    // Writes `value` into a rectangle of the stencil buffer (in pixels), e.g. to mask the 3D view behind a HUD panel.
+    pub fn fill_stencil_rect(&mut self, x: usize, y: usize, w: usize, h: usize, value: u8) {
+        let (sw, sh, n) = (self.sample_w(), self.sample_h(), self.samples);
+
+        for y in (y * n).min(sh)..((y + h) * n).min(sh) {
+            for x in (x * n).min(sw)..((x + w) * n).min(sw) {
+                self.stencil_buf[y * sw + x] = value;
+            }
+        }
+    }
+
+    // Runs the stencil test for a pixel. On failure the fail operation is applied and false is returned.
+    fn stencil_test(&mut self, index: usize) -> bool {
+        let Some(stencil) = self.stencil else {
+            return true;
+        };
+
+        if stencil.test(self.stencil_buf[index]) {
+            return true;
+        }
+
+        stencil.update(&mut self.stencil_buf[index], stencil.fail_op);
+        false
+    }
+
+    fn stencil_update(&mut self, index: usize, depth_passed: bool) {
+        if let Some(stencil) = self.stencil {
+            let op = if depth_passed { stencil.pass_op } else { stencil.depth_fail_op };
+            stencil.update(&mut self.stencil_buf[index], op);
+        }
+    }
+
+    // Downsamples the sample buffer into `pixel_buf`, runs post-processing and encodes the pixels into `char_buf`.
+    pub fn resolve(&mut self) {
+        self.resolve_pixels();
+        self.encode_cells();
+    }
+
+    // Downsamples the sample buffer into `pixel_buf` and runs post-processing. Enough for offscreen
+    // targets that are only composited with `blit` or sampled through `pixel_image`.
+    pub fn resolve_pixels(&mut self) {
+        for y in 0..self.h {
             for x in 0..self.w {
-                str.push_str(&self.char_buf[y * self.w + x].to_ansi(mode));
+                self.pixel_buf[y * self.w + x] = self.resolve_pixel(x, y);
+                self.pixel_z_buf[y * self.w + x] = self.resolve_depth(x, y);
+            }
+        }
+
+        self.post.apply(&mut self.pixel_buf, &self.pixel_z_buf, self.w, self.h);
+    }
+
+    // Copies the resolved pixels of `src` into `dst` (in pixels) of this framebuffer's resolved pixels,
+    // scaled to fit. Pixels `src` left empty stay transparent. Call between `resolve_pixels` and `encode_cells`.
+    pub fn blit(&mut self, src: &Framebuf, dst: Rect) {
+        let visible = dst.intersect(&Rect::new(0, 0, self.w, self.h));
+
+        if src.w == 0 || src.h == 0 {
+            return;
+        }
+
+        for y in visible.y..visible.y + visible.h {
+            let sy = (y - dst.y) * src.h / dst.h;
+
+            for x in visible.x..visible.x + visible.w {
+                let sx = (x - dst.x) * src.w / dst.w;
+
+                if let Some(color) = src.pixel_buf[sy * src.w + sx] {
+                    self.pixel_buf[y * self.w + x] = Some(color);
+                    self.pixel_z_buf[y * self.w + x] = src.pixel_z_buf[sy * src.w + sx];
+                }
+            }
+        }
+    }
+
+    // Encodes the resolved pixels into `char_buf` for the current cell mode.
+    pub fn encode_cells(&mut self) {
+        let (cols, rows) = (self.cols(), self.rows());
+        self.char_buf.resize(cols * rows, Default::default());
+
+        for row in 0..rows {
+            for col in 0..cols {
+                self.char_buf[row * cols + col] = match self.cell_mode {
+                    CellMode::HalfBlock => CharInfo::half_block(
+                        self.pixel_buf[2 * row * self.w + col], 
+                        self.pixel_buf[(2 * row + 1) * self.w + col]),
+                    CellMode::Braille => CharInfo::braille(
+                        &std::array::from_fn(|i| self.pixel_buf[(4 * row + i / 2) * self.w + 2 * col + i % 2])),
+                    CellMode::Ascii => CharInfo::ascii(
+                        self.pixel_buf[2 * row * self.w + col], 
+                        self.pixel_buf[(2 * row + 1) * self.w + col])
+                };
+            }
+        }
+    }
+
+    // Nearest depth of the samples inside a pixel.
+    fn resolve_depth(&self, x: usize, y: usize) -> f32 {
+        let n = self.samples;
+        let mut depth = f32::INFINITY;
+
+        for sy in y * n..(y + 1) * n {
+            for sx in x * n..(x + 1) * n {
+                depth = depth.min(self.z_buf[sy * self.sample_w() + sx]);
+            }
+        }
+
+        depth
+    }
+
+    fn resolve_pixel(&self, x: usize, y: usize) -> Option<CharColor> {
+        if self.samples == 1 {
+            return self.color_buf[y * self.w + x];
+        }
+
+        let n = self.samples as f32;
+        let radius = self.filter.radius(n);
+        let center = vec2((x as f32 + 0.5) * n, (y as f32 + 0.5) * n);
+
+        let min_x = (center.x - radius).floor().max(0.0) as usize;
+        let max_x = ((center.x + radius).ceil() as usize).min(self.sample_w());
+        let min_y = (center.y - radius).floor().max(0.0) as usize;
+        let max_y = ((center.y + radius).ceil() as usize).min(self.sample_h());
+
+        let mut total_weight = 0.0;
+        let mut covered_weight = 0.0;
+        let mut sum = vec3(0.0, 0.0, 0.0);
+
+        for sy in min_y..max_y {
+            for sx in min_x..max_x {
+                let weight = self.filter.weight((sx as f32 + 0.5 - center.x).abs(), n) * 
+                    self.filter.weight((sy as f32 + 0.5 - center.y).abs(), n);
+
+                if weight <= 0.0 {
+                    continue;
+                }
+
+                total_weight += weight;
+
+                if let Some(color) = self.color_buf[sy * self.sample_w() + sx] {
+                    covered_weight += weight;
+                    sum += weight * vec3(color.r as f32, color.g as f32, color.b as f32);
+                }
+            }
+        }
+
+        // The terminal background is unknown so edges can't be blended against it, 
+        // instead a pixel is drawn if it is at least half covered.
+        if covered_weight * 2.0 < total_weight {
+            return None;
+        }
+
+        let color = sum / covered_weight;
+        Some(CharColor { r: color.x.round() as u8, g: color.y.round() as u8, b: color.z.round() as u8 })
+    }
+
+    // Resolved pixels before cell encoding, uncovered pixels get `background`. Also how offscreen
+    // targets become textures for shaders, e.g. a security camera feed shown on a monitor mesh.
+    pub fn pixel_image(&self, background: CharColor) -> Texture {
+        Texture::new(self.w, self.h, self.pixel_buf.iter().map(|p| p.unwrap_or(background)).collect())
+    }
+
+    // The encoded cells as a terminal would show them, each cell drawn as `cell_w` x `cell_h` pixels.
+    pub fn cell_image(&self, cell_w: usize, cell_h: usize, background: CharColor) -> Texture {
+        let (cols, rows) = (self.cols(), self.rows());
+        let (w, h) = (cols * cell_w, rows * cell_h);
+        let mut pixels = Vec::with_capacity(w * h);
+
+        for y in 0..h {
+            for x in 0..w {
+                let c = &self.char_buf[(y / cell_h) * cols + x / cell_w];
+                let u = (x % cell_w) as f32 / cell_w as f32 + 0.5 / cell_w as f32;
+                let v = (y % cell_h) as f32 / cell_h as f32 + 0.5 / cell_h as f32;
+
+                pixels.push(match (c.covers(u, v), c.fg_color, c.bg_color) {
+                    (true, Some(fg), _) => fg,
+                    (_, _, Some(bg)) => bg,
+                    _ => background
+                });
+            }
+        }
+
+        Texture::new(w, h, pixels)
+    }
+
+    pub fn to_string(&self, mode: &AnsiColorMode) -> String {
+        let (cols, rows) = (self.cols(), self.rows());
+        let mut str = String::with_capacity(cols * rows * 45 + rows * 2);
+
+        for y in 0..rows {
+            for x in 0..cols {
+                str.push_str(&self.char_buf[y * cols + x].to_ansi(mode));
             }
 
             str.push_str("\r\n");
@@ -65,16 +459,36 @@ impl Framebuf {
         str
     }
 
+    // Origin and size of the viewport in samples.
+    fn sample_viewport(&self) -> (f32, f32, f32, f32) {
+        let viewport = self.viewport_rect();
+        let n = self.samples;
+
+        ((viewport.x * n) as f32, (viewport.y * n) as f32, (viewport.w * n) as f32, (viewport.h * n) as f32)
+    }
+
     fn prepare_position(&self, p: &Vec4) -> Vec4 {
+        let (x, y, w, h) = self.sample_viewport();
+
         // W division (homogeneous clip space -> NDC space).
-        // Viewport transformation ([-1, 1] -> framebuffer size).
+        // Viewport transformation ([-1, 1] -> viewport in the sample buffer).
         vec4(
-            (p.x / p.w + 1.0) / 2.0 * (self.w - 1) as f32, 
-            (p.y / p.w + 1.0) / 2.0 * (self.h - 1) as f32, 
+            x + (p.x / p.w + 1.0) / 2.0 * (w - 1.0), 
+            y + (p.y / p.w + 1.0) / 2.0 * (h - 1.0), 
             p.z / p.w, 
             p.w)
     }
 
+    // Runs stencil testing and shades a sample.
+    fn shade_sample(&mut self, index: usize, vertex: &Vertex, shader: Shader) {
+        if !self.stencil_test(index) {
+            return;
+        }
+
+        self.stencil_update(index, true);
+        self.color_buf[index] = Some(shader(vertex));
+    }
+
     pub fn draw_line(&mut self, start: &Vertex, end: &Vertex, shader: Shader) {        
         if is_point_visible(start.position) && is_point_visible(end.position) {
             self.raster_line(start, end, shader);
@@ -96,20 +510,26 @@ impl Framebuf {
         
         let mut current = Vertex { position: start_pos, attributes: start.attributes.clone() };
         let increment = &difference / num_steps;
+        let bounds = self.sample_bounds();
         
         for _ in 0..num_steps {
             let x = current.position.x as usize;
             let y = current.position.y as usize;
 
             // TODO: replace with proper clipping.
-            if x >= self.w || y >= self.h {
+            if x < bounds.x || y < bounds.y || x >= bounds.x + bounds.w || y >= bounds.y + bounds.h {
+                current += &increment;
                 continue;
             }
 
-            shader(
-                &current, 
-                &mut self.char_buf[(y / 2) * self.w + x], 
-                if y % 2 == 0 { &CharHalf::Top } else { &CharHalf::Bottom });
+            // Lines cover every sample of the pixels they pass through so they survive the resolve.
+            let n = self.samples;
+
+            for sy in (y / n * n)..(y / n * n + n) {
+                for sx in (x / n * n)..(x / n * n + n) {
+                    self.shade_sample(sy * self.sample_w() + sx, &current, shader);
+                }
+            }
 
             current += &increment;
         }
@@ -118,7 +538,7 @@ impl Framebuf {
     pub fn draw_triangle(&mut self, vertices: &[&Vertex; 3], shader: Shader) {
         // Raster triangle without clipping if all vertices are visible
         if !vertices.iter().map(|v| is_point_visible(v.position)).collect::<Vec<bool>>().contains(&false) {
-            self.raster_triangle(&vertices, shader);
+            self.raster_triangle(vertices, shader);
             return;
         }
 
@@ -139,11 +559,17 @@ impl Framebuf {
         let p = vertices.iter().map(|v| self.prepare_position(&v.position)).collect::<Vec<Vec4>>();
         let area_inv = 1.0 / edge_func(&p[0].xy(), &p[1].xy(), &p[2].xy());
 
-        // Calculate bounding box
-        let min_x = p.iter().map(|p| p.x).fold(f32::INFINITY, f32::min).max(0.0) as usize;
-        let max_x = p.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max).min(self.w as f32 - 1.0) as usize;
-        let min_y = p.iter().map(|p| p.y).fold(f32::INFINITY, f32::min).max(0.0) as usize;
-        let max_y = p.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max).min(self.h as f32 - 1.0) as usize;
+        // Calculate bounding box, limited to the viewport and scissor rectangle
+        let bounds = self.sample_bounds();
+
+        if bounds.w == 0 || bounds.h == 0 {
+            return;
+        }
+
+        let min_x = p.iter().map(|p| p.x).fold(f32::INFINITY, f32::min).max(bounds.x as f32) as usize;
+        let max_x = p.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max).min((bounds.x + bounds.w) as f32 - 1.0) as usize;
+        let min_y = p.iter().map(|p| p.y).fold(f32::INFINITY, f32::min).max(bounds.y as f32) as usize;
+        let max_y = p.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max).min((bounds.y + bounds.h) as f32 - 1.0) as usize;
 
         for x in min_x..=max_x {
             for y in min_y..=max_y {
@@ -161,12 +587,21 @@ impl Framebuf {
 
                 let z = p[0].z * bc[0] + p[1].z * bc[1] + p[2].z * bc[2];
 
+                let index = y * self.sample_w() + x;
+
+                // Stencil testing
+                if !self.stencil_test(index) {
+                    continue;
+                }
+
                 // Depth testing
-                if z >= self.z_buf[y * self.w + x] {
+                if z >= self.z_buf[index] {
+                    self.stencil_update(index, false);
                     continue;
                 }
 
-                self.z_buf[y * self.w + x] = z;
+                self.stencil_update(index, true);
+                self.z_buf[index] = z;
 
                 // Interpolate vertex using barycentric coordinates
 
@@ -183,49 +618,82 @@ impl Framebuf {
                         .collect()
                 };
 
-                shader(
-                    &vertex, 
-                    &mut self.char_buf[(y / 2) * self.w + x], 
-                    if y % 2 == 0 { &CharHalf::Top } else { &CharHalf::Bottom });
+                self.color_buf[index] = Some(shader(&vertex));
             }
         }
     }
 
-    pub fn draw_model(&mut self, model: &Model, model_matrix: &Mat4, vp_matrix: &Mat4, normal_matrix: &Mat3, camera_pos: &Vec3, shader: Shader) {
-        let mesh = &model.mesh;
+    pub fn draw_model(&mut self, mesh: &Mesh, model_matrix: &Mat4, vp_matrix: &Mat4, normal_matrix: &Mat3, camera_pos: &Vec3, shader: Shader) {
+        // Skip meshes that are entirely outside the view before touching any triangles.
+        if !mesh.is_visible(model_matrix, vp_matrix) {
+            return;
+        }
+
+        self.draw_instance(mesh, &Instance::new(*model_matrix), normal_matrix, vp_matrix, camera_pos, shader);
+    }
 
-        for indices in mesh.indices.chunks(3) {     
-            let get_position = |i| vec4(
-                mesh.positions[3 * i as usize],
-                mesh.positions[3 * i as usize + 1],
-                mesh.positions[3 * i as usize + 2],
-                1.0,
-            );
+    // Draws the mesh once per instance, e.g. trees of a forest or the people of a crowd. Instances
+    // outside the view are skipped by their bounding sphere against a frustum shared by all of them.
+    pub fn draw_instanced(&mut self, mesh: &Mesh, instances: &[Instance], vp_matrix: &Mat4, camera_pos: &Vec3, shader: Shader) {
+        let frustum = Frustum::from_matrix(vp_matrix);
 
-            if should_backface_cull(&[get_position(indices[0]), get_position(indices[1]), get_position(indices[2])], model_matrix, camera_pos) {
+        for instance in instances {
+            if !frustum.intersects_sphere(&mesh.sphere.transform(&instance.model_matrix)) {
                 continue;
             }
 
-            let get_attributes = |i| {
-                let normal = normal_matrix * vec3(
-                    mesh.normals[3 * i as usize], 
-                    mesh.normals[3 * i as usize + 1], 
-                    mesh.normals[3 * i as usize + 2]
-                );
+            let normal_matrix = normal_matrix(&instance.model_matrix);
+            self.draw_instance(mesh, instance, &normal_matrix, vp_matrix, camera_pos, shader);
+        }
+    }
 
-                vec![mesh.texcoords[2 * i as usize], mesh.texcoords[2 * i as usize + 1], 
-                    normal[0], normal[1], normal[2]]
-            };
+    fn draw_instance(&mut self, mesh: &Mesh, instance: &Instance, normal_matrix: &Mat3, vp_matrix: &Mat4, camera_pos: &Vec3, shader: Shader) {
+        let mvp_matrix = vp_matrix * instance.model_matrix;
+
+        let positions = mesh.positions.chunks_exact(3)
+            .map(|p| vec4(p[0], p[1], p[2], 1.0))
+            .collect::<Vec<_>>();
+
+        // Every vertex goes through the vertex stage once, triangles sharing it reuse the result.
+        let clip_positions = positions.iter().map(|p| mvp_matrix * p).collect::<Vec<_>>();
+
+        // Shaders get uv, normal, linear vertex color times the instance tint and the instance phase.
+        // Missing attributes (see `Mesh::validate`) read as zero, missing colors as white.
+        let attributes = (0..positions.len()).map(|i| {
+            let normal = normal_matrix * mesh.normals.get(3 * i..3 * i + 3).map_or_else(Vec3::zeros, make_vec3);
+            let uv = mesh.texcoords.get(2 * i..2 * i + 2).unwrap_or(&[0.0; 2]);
+            let color = mesh.colors.get(3 * i..3 * i + 3).unwrap_or(&[1.0; 3]);
 
-            let mvp_matrix = vp_matrix * model_matrix;
+            vec![uv[0], uv[1], normal[0], normal[1], normal[2],
+                color[0] * instance.tint.r, color[1] * instance.tint.g, color[2] * instance.tint.b, instance.phase]
+        }).collect::<Vec<_>>();
 
-            let v0 = Vertex { position: mvp_matrix * get_position(indices[0]), attributes: get_attributes(indices[0]) };
-            let v1 = Vertex { position: mvp_matrix * get_position(indices[1]), attributes: get_attributes(indices[1]) };
-            let v2 = Vertex { position: mvp_matrix * get_position(indices[2]), attributes: get_attributes(indices[2]) };
+        for indices in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [indices[0] as usize, indices[1] as usize, indices[2] as usize];
+
+            if a.max(b).max(c) >= positions.len() {
+                continue;
+            }
+
+            if should_backface_cull(&[positions[a], positions[b], positions[c]], &instance.model_matrix, camera_pos) {
+                continue;
+            }
+
+            let v0 = Vertex { position: clip_positions[a], attributes: attributes[a].clone() };
+            let v1 = Vertex { position: clip_positions[b], attributes: attributes[b].clone() };
+            let v2 = Vertex { position: clip_positions[c], attributes: attributes[c].clone() };
 
             self.draw_triangle(&[&v0, &v1, &v2], shader);
         }
     }
+
+    // Deforms the mesh by the joint matrices of its skin (see `Skin::joint_matrices`) before drawing it.
+    pub fn draw_skinned_model(&mut self, mesh: &Mesh, joint_matrices: &[Mat4], model_matrix: &Mat4, vp_matrix: &Mat4, camera_pos: &Vec3, shader: Shader) {
+        let skinned = skin_mesh(mesh, joint_matrices);
+        let normal_matrix = normal_matrix(model_matrix);
+
+        self.draw_model(&skinned, model_matrix, vp_matrix, &normal_matrix, camera_pos, shader);
+    }
 }
 
 fn is_point_visible(p: Vec4) -> bool {
@@ -235,3 +703,87 @@ fn is_point_visible(p: Vec4) -> bool {
 fn edge_func(a: &Vec2, b: &Vec2, c: &Vec2) -> f32 {
     (c.x - a.x) * (b.y - a.y) - (c.y - a.y) * (b.x - a.x)
 }
+
+#[cfg(test)]
+mod tests {
+    use glm::vec4;
+
+    use super::*;
+    use crate::stencil::{StencilFunc, StencilOp};
+
+    const RED: CharColor = CharColor { r: 255, g: 0, b: 0 };
+
+    fn corner(x: f32, y: f32, z: f32) -> Vertex {
+        Vertex { position: vec4(x, y, z, 1.0), attributes: Vec::new() }
+    }
+
+    // One triangle through three corners of the viewport at depth `z`.
+    fn draw_half(fb: &mut Framebuf, z: f32) {
+        fb.draw_triangle(&[&corner(-1.0, -1.0, z), &corner(1.0, -1.0, z), &corner(1.0, 1.0, z)], &|_: &Vertex| RED);
+    }
+
+    // Two triangles covering the whole viewport at depth `z`.
+    fn draw_quad(fb: &mut Framebuf, z: f32) {
+        draw_half(fb, z);
+        fb.draw_triangle(&[&corner(-1.0, -1.0, z), &corner(1.0, 1.0, z), &corner(-1.0, 1.0, z)], &|_: &Vertex| RED);
+    }
+
+    #[test]
+    fn stencil_masks_draws() {
+        let mut unmasked = Framebuf::new(8, 8);
+        unmasked.clear();
+        draw_quad(&mut unmasked, 0.0);
+        let covered = |i: usize| unmasked.color_buf[i].is_some();
+        assert!((0..64).filter(|i| covered(*i)).count() > 32);
+
+        let mut fb = Framebuf::new(8, 8);
+        fb.clear();
+        fb.fill_stencil_rect(0, 0, 4, 8, 1);
+        fb.stencil = Some(StencilState::equal(1));
+        draw_quad(&mut fb, 0.0);
+
+        for i in 0..64 {
+            assert_eq!(fb.color_buf[i].is_some(), covered(i) && i % 8 < 4, "pixel {}, {}", i % 8, i / 8);
+        }
+
+        // Samples failing the stencil test get the fail op, the rest the pass op. A single triangle
+        // so no sample is drawn twice.
+        let mut half = Framebuf::new(8, 8);
+        half.clear();
+        draw_half(&mut half, 0.0);
+
+        fb.stencil = Some(StencilState { fail_op: StencilOp::Replace, pass_op: StencilOp::Increment, ..StencilState::equal(1) });
+        fb.clear_stencil(0);
+        fb.fill_stencil_rect(0, 0, 4, 8, 1);
+        draw_half(&mut fb, -0.5);
+
+        for i in 0..64 {
+            let expected = match (half.color_buf[i].is_some(), i % 8 < 4) {
+                (false, inside) => inside as u8,
+                (true, true) => 2,
+                (true, false) => 1
+            };
+
+            assert_eq!(fb.stencil_buf[i], expected, "pixel {}, {}", i % 8, i / 8);
+        }
+    }
+
+    #[test]
+    fn depth_fail_op_runs_for_hidden_samples() {
+        let mut fb = Framebuf::new(4, 4);
+        fb.clear();
+        draw_half(&mut fb, 0.0);
+
+        fb.stencil = Some(StencilState {
+            func: StencilFunc::Always,
+            depth_fail_op: StencilOp::IncrementWrap,
+            pass_op: StencilOp::Zero,
+            ..StencilState::write(9)
+        });
+        draw_half(&mut fb, 0.5);
+
+        for (stencil, color) in fb.stencil_buf.iter().zip(&fb.color_buf) {
+            assert_eq!(*stencil, color.is_some() as u8);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path, time::{Instant, SystemTime, UNIX_EPOCH}};
+
+// Writes a terminal byte stream as an asciicast v2 file (https://docs.asciinema.org/manual/asciicast/v2/),
+// playable with `asciinema play`.
+pub struct CastRecorder {
+    writer: BufWriter<File>,
+    // Set once the header has been written, event times are relative to it.
+    start: Option<Instant>,
+    width: u32,
+    height: u32,
+    term: Option<String>
+}
+
+impl CastRecorder {
+    pub fn create<P: AsRef<Path>>(path: P, width: u32, height: u32) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            start: None,
+            width,
+            height,
+            term: None
+        })
+    }
+
+    pub fn set_term(&mut self, term: &str) {
+        self.term = Some(term.to_owned());
+    }
+
+    // The header is written lazily so the size requested by the client ends up in it
+    // instead of a resize event at time zero.
+    pub fn resize(&mut self, width: u32, height: u32) -> io::Result<()> {
+        self.width = width;
+        self.height = height;
+
+        if self.start.is_some() {
+            self.write_event("r", &format!("{width}x{height}"))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn output(&mut self, data: &str) -> io::Result<()> {
+        self.write_event("o", data)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn write_event(&mut self, code: &str, data: &str) -> io::Result<()> {
+        let start = match self.start {
+            Some(start) => start,
+            None => self.write_header()?
+        };
+
+        writeln!(self.writer, "[{:.6}, \"{code}\", \"{}\"]", start.elapsed().as_secs_f64(), escape_json(data))
+    }
+
+    fn write_header(&mut self) -> io::Result<Instant> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let env = match &self.term {
+            Some(term) => format!(", \"env\": {{\"TERM\": \"{}\"}}", escape_json(term)),
+            None => String::new()
+        };
+
+        writeln!(self.writer, "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {timestamp}{env}}}",
+            self.width, self.height)?;
+
+        let start = Instant::now();
+        self.start = Some(start);
+        Ok(start)
+    }
+}
+
+impl Drop for CastRecorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+fn escape_json(str: &str) -> String {
+    let mut escaped = String::with_capacity(str.len());
+
+    for c in str.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+
+    escaped
+}
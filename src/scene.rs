@@ -0,0 +1,353 @@
+use std::path::Path;
+
+use glm::{make_vec2, quat, vec3, vec4, Mat4, Vec2, Vec3};
+
+use crate::{anim::{Animation, Channel, Interpolation, Keyframes, Pose, Skeleton, Skin, Transform}, morph::{morph_mesh, MorphTarget}, char::CharColor, color::LinearColor, error::EngineError, mesh::{Mesh, NormalMode}, raster::{normal_matrix, Framebuf, Shader}, texture::Texture, vertex::Vertex};
+
+// Metallic-roughness material, only the base color is used for shading so far. `Scene::draw_pose`
+// passes it to shaders as the vertex color.
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: String,
+    pub base_color: LinearColor,
+    pub alpha: f32,
+    // Index into `Scene::textures`, multiplied with `base_color`.
+    pub base_color_texture: Option<usize>,
+    pub metallic: f32,
+    pub roughness: f32
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            base_color: LinearColor::WHITE,
+            alpha: 1.0,
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0
+        }
+    }
+}
+
+impl Material {
+    pub fn base_color_at(&self, textures: &[Texture], uv: &Vec2) -> LinearColor {
+        match self.base_color_texture.and_then(|i| textures.get(i)) {
+            Some(texture) => self.base_color * LinearColor::from_srgb(&texture.sample(uv)),
+            None => self.base_color
+        }
+    }
+    // Copy of `vertex` with the base color at its texture coordinates multiplied into the color
+    // attributes, so shaders that use vertex colors show the material.
+    pub fn apply(&self, textures: &[Texture], vertex: &Vertex) -> Vertex {
+        let mut vertex = vertex.clone();
+        let uv = vertex.attributes.get(0..2).map_or_else(Vec2::zeros, make_vec2);
+        let color = self.base_color_at(textures, &uv);
+
+        if let Some(colors) = vertex.attributes.get_mut(5..8) {
+            for (attribute, channel) in colors.iter_mut().zip([color.r, color.g, color.b]) {
+                *attribute *= channel;
+            }
+        }
+
+        vertex
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    // Relative to the parent.
+    pub transform: Transform,
+    // Indices into `Scene::meshes`, glTF meshes are split into one mesh per primitive.
+    pub meshes: Vec<usize>,
+    pub skin: Option<usize>
+}
+
+// Node hierarchy with everything the nodes refer to by index.
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    pub nodes: Vec<Node>,
+    pub roots: Vec<usize>,
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<Texture>,
+    pub skins: Vec<Skin>,
+    pub animations: Vec<Animation>
+}
+
+impl Scene {
+    // One root node per mesh, e.g. to draw OBJ, STL or PLY models the same way as glTF scenes.
+    pub fn from_meshes(meshes: Vec<Mesh>) -> Self {
+        let nodes = (0..meshes.len())
+            .map(|i| Node { name: meshes[i].name.clone(), parent: None, children: Vec::new(), transform: Transform::default(), meshes: vec![i], skin: None })
+            .collect::<Vec<_>>();
+
+        Self {
+            roots: (0..nodes.len()).collect(),
+            nodes,
+            meshes,
+            ..Default::default()
+        }
+    }
+
+    // Loads a .gltf with external or embedded buffers, or a .glb. Only triangle primitives are kept.
+    pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<Self, EngineError> {
+        use gltf::animation::util::ReadOutputs;
+
+        let (document, buffers, images) = gltf::import(path)?;
+        let buffer = |b: gltf::Buffer| Some(&*buffers[b.index()]);
+
+        let textures = images.iter().map(texture_from_image).collect();
+
+        let materials = document.materials().map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            let [r, g, b, a] = pbr.base_color_factor();
+
+            Material {
+                name: material.name().unwrap_or_default().to_owned(),
+                base_color: LinearColor::new(r, g, b),
+                alpha: a,
+                base_color_texture: pbr.base_color_texture().map(|info| info.texture().source().index()),
+                metallic: pbr.metallic_factor(),
+                roughness: pbr.roughness_factor()
+            }
+        }).collect();
+
+        let mut meshes = Vec::new();
+        let mut mesh_primitives = Vec::new();
+
+        for mesh in document.meshes() {
+            let mut primitives = Vec::new();
+
+            for primitive in mesh.primitives().filter(|p| p.mode() == gltf::mesh::Mode::Triangles) {
+                let reader = primitive.reader(buffer);
+                let positions = reader.read_positions().into_iter().flatten().flatten().collect::<Vec<f32>>();
+                let count = positions.len() / 3;
+
+                let normals = reader.read_normals().map_or_else(Vec::new, |n| n.flatten().collect());
+                let texcoords = reader.read_tex_coords(0).map_or_else(Vec::new, |t| t.into_f32().flatten().collect());
+                let indices = reader.read_indices()
+                    .map_or_else(|| (0..count as u32).collect(), |i| i.into_u32().collect());
+
+                let mut result = Mesh::new(mesh.name().unwrap_or_default().to_owned(), positions, normals, texcoords, indices);
+                result.material = primitive.material().index();
+
+                result.morph_targets = reader.read_morph_targets().map(|(positions, normals, _)| MorphTarget {
+                    position_deltas: positions.into_iter().flatten().flatten().collect(),
+                    normal_deltas: normals.into_iter().flatten().flatten().collect()
+                }).collect();
+                result.morph_weights = mesh.weights().map(|w| w.to_vec()).unwrap_or_else(|| vec![0.0; result.morph_targets.len()]);
+
+                if let Some(colors) = reader.read_colors(0) {
+                    result.colors = colors.into_rgb_f32().flatten().collect();
+                }
+
+                if let (Some(joints), Some(weights)) = (reader.read_joints(0), reader.read_weights(0)) {
+                    result.joints = joints.into_u16().flatten().collect();
+                    result.weights = weights.into_f32().flatten().collect();
+                }
+
+                // glTF asks for flat normals when a primitive has none.
+                result.generate_missing(NormalMode::Flat);
+                result.validate()?;
+
+                primitives.push(meshes.len());
+                meshes.push(result);
+            }
+
+            mesh_primitives.push(primitives);
+        }
+
+        let mut nodes = document.nodes().map(|node| {
+            let (t, r, s) = node.transform().decomposed();
+
+            Node {
+                name: node.name().unwrap_or_default().to_owned(),
+                parent: None,
+                children: node.children().map(|c| c.index()).collect(),
+                transform: Transform {
+                    translation: Vec3::from(t),
+                    rotation: quat(r[0], r[1], r[2], r[3]),
+                    scale: Vec3::from(s)
+                },
+                meshes: node.mesh().map(|m| mesh_primitives[m.index()].clone()).unwrap_or_default(),
+                skin: node.skin().map(|s| s.index())
+            }
+        }).collect::<Vec<Node>>();
+
+        for i in 0..nodes.len() {
+            for child in nodes[i].children.clone() {
+                nodes[child].parent = Some(i);
+            }
+        }
+
+        let roots = match document.default_scene().or_else(|| document.scenes().next()) {
+            Some(scene) => scene.nodes().map(|n| n.index()).collect(),
+            None => (0..nodes.len()).filter(|i| nodes[*i].parent.is_none()).collect()
+        };
+
+        let skins = document.skins().map(|skin| {
+            let joints = skin.joints().map(|j| j.index()).collect::<Vec<usize>>();
+            let inverse_bind_matrices = match skin.reader(buffer).read_inverse_bind_matrices() {
+                Some(matrices) => matrices.map(Mat4::from).collect(),
+                None => vec![Mat4::identity(); joints.len()]
+            };
+
+            Skin {
+                name: skin.name().unwrap_or_default().to_owned(),
+                joints,
+                inverse_bind_matrices,
+                skeleton: skin.skeleton().map(|n| n.index())
+            }
+        }).collect();
+
+        let animations = document.animations().map(|animation| {
+            let channels = animation.channels().filter_map(|channel| {
+                let reader = channel.reader(buffer);
+
+                let keyframes = match reader.read_outputs()? {
+                    ReadOutputs::Translations(t) => Keyframes::Translation(t.map(Vec3::from).collect()),
+                    ReadOutputs::Rotations(r) =>
+                        Keyframes::Rotation(r.into_f32().map(|[x, y, z, w]| quat(x, y, z, w)).collect()),
+                    ReadOutputs::Scales(s) => Keyframes::Scale(s.map(Vec3::from).collect()),
+                    ReadOutputs::MorphTargetWeights(w) => Keyframes::Weights(w.into_f32().collect())
+                };
+
+                Some(Channel {
+                    node: channel.target().node().index(),
+                    times: reader.read_inputs()?.collect(),
+                    keyframes,
+                    interpolation: match channel.sampler().interpolation() {
+                        gltf::animation::Interpolation::Step => Interpolation::Step,
+                        gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline
+                    }
+                })
+            }).collect();
+
+            Animation::new(animation.name().unwrap_or_default().to_owned(), channels)
+        }).collect();
+
+        Ok(Self { nodes, roots, meshes, materials, textures, skins, animations })
+    }
+
+    // Node to world transform of every node, by node index.
+    pub fn world_matrices(&self) -> Vec<Mat4> {
+        let mut matrices = vec![Mat4::identity(); self.nodes.len()];
+        let mut stack = self.roots.iter().map(|i| (*i, Mat4::identity())).collect::<Vec<_>>();
+
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            matrices[index] = parent * node.transform.matrix();
+            stack.extend(node.children.iter().map(|c| (*c, matrices[index])));
+        }
+
+        matrices
+    }
+
+    // Copies of all meshes with the node transforms applied, e.g. to preview a scene as a single model.
+    pub fn baked_meshes(&self) -> Vec<Mesh> {
+        let world_matrices = self.world_matrices();
+        let mut meshes = Vec::new();
+
+        for (node, world_matrix) in self.nodes.iter().zip(&world_matrices) {
+            let normal_matrix = normal_matrix(world_matrix);
+
+            for mesh in node.meshes.iter().map(|i| &self.meshes[*i]) {
+                let mut mesh = mesh.clone();
+
+                for p in mesh.positions.chunks_mut(3) {
+                    let world = world_matrix * vec4(p[0], p[1], p[2], 1.0);
+                    p.copy_from_slice(&[world.x, world.y, world.z]);
+                }
+
+                for n in mesh.normals.chunks_mut(3) {
+                    let world = normal_matrix * vec3(n[0], n[1], n[2]);
+                    n.copy_from_slice(world.as_slice());
+                }
+
+                mesh.update_bounds();
+                meshes.push(mesh);
+            }
+        }
+
+        meshes
+    }
+
+    // Pose with the transforms and morph weights of the nodes as loaded.
+    pub fn rest_pose(&self) -> Pose {
+        Skeleton::from_scene(self).rest_pose()
+    }
+
+    pub fn draw(&self, fb: &mut Framebuf, vp_matrix: &Mat4, camera_pos: &Vec3, shader: Shader) {
+        self.draw_pose(fb, &self.rest_pose(), &Mat4::identity(), vp_matrix, camera_pos, shader);
+    }
+
+    // Draws the scene in a pose of `Skeleton::from_scene`, e.g. sampled from one of its animations. Morph
+    // targets and skins are applied before drawing. `model_matrix` places the whole scene in the world.
+    pub fn draw_pose(&self, fb: &mut Framebuf, pose: &Pose, model_matrix: &Mat4, vp_matrix: &Mat4, camera_pos: &Vec3, shader: Shader) {
+        let world_matrices = Skeleton::from_scene(self).world_matrices(pose);
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            for mesh in node.meshes.iter().map(|i| &self.meshes[*i]) {
+                let weights = pose.weights.get(i).filter(|w| !w.is_empty()).unwrap_or(&mesh.morph_weights);
+                let morphed = (!mesh.morph_targets.is_empty()).then(|| morph_mesh(mesh, weights));
+                let mesh = morphed.as_ref().unwrap_or(mesh);
+                let material = mesh.material.and_then(|i| self.materials.get(i));
+                let with_material = |vertex: &Vertex| match material {
+                    Some(material) => shader(&material.apply(&self.textures, vertex)),
+                    None => shader(vertex)
+                };
+                let shader: Shader = &with_material;
+
+                match node.skin.and_then(|i| self.skins.get(i)).filter(|_| !mesh.joints.is_empty()) {
+                    // Skinned vertices are already in scene space, the node's own transform doesn't apply.
+                    Some(skin) => fb.draw_skinned_model(mesh, &skin.joint_matrices(&world_matrices), model_matrix, vp_matrix, camera_pos, shader),
+                    None => {
+                        let world_matrix = model_matrix * world_matrices[i];
+                        let normal_matrix = normal_matrix(&world_matrix);
+
+                        fb.draw_model(mesh, &world_matrix, vp_matrix, &normal_matrix, camera_pos, shader);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Keeps the most significant byte of each channel, gray images (with or without alpha) are expanded to rgb.
+fn texture_from_image(image: &gltf::image::Data) -> Texture {
+    use gltf::image::Format;
+
+    let (channels, bytes) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4)
+    };
+
+    let channel = |pixel: &[u8], i: usize| -> u8 {
+        let i = if channels < 3 { 0 } else { i * bytes };
+
+        match bytes {
+            1 => pixel[i],
+            2 => u16::from_le_bytes([pixel[i], pixel[i + 1]]).to_be_bytes()[0],
+            _ => (f32::from_le_bytes([pixel[i], pixel[i + 1], pixel[i + 2], pixel[i + 3]]).clamp(0.0, 1.0) * 255.0) as u8
+        }
+    };
+
+    let pixels = image.pixels.chunks(channels * bytes)
+        .map(|p| CharColor { r: channel(p, 0), g: channel(p, 1), b: channel(p, 2) })
+        .collect();
+
+    Texture::new(image.width as usize, image.height as usize, pixels)
+}
@@ -1,17 +1,22 @@
-use std::{collections::HashMap, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+use std::{collections::HashMap, io, net::SocketAddr, path::{Path, PathBuf}, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use russh::{server::{Auth, Config, Handle, Handler, Msg, Server, Session}, Channel, ChannelId, CryptoVec};
+use glm::Vec3;
+use russh::{server::{Auth, Config, Handle, Handler, Msg, Server, Session}, Channel, ChannelId, CryptoVec, Pty};
 use russh_keys::key::PublicKey;
-use tokio::sync::Mutex;
+use tokio::{net::TcpListener, sync::Mutex};
 use uuid::Uuid;
 
-use crate::{char::ANSI_CLEAR_SCREEN, client::Client};
+use crate::{camera::Camera, char::{AnsiColorMode, ANSI_CLEAR_SCREEN, ANSI_HIDE_CURSOR, ANSI_MOVE_CURSOR_TO_START, ANSI_QUERY_CELL_SIZE}, client::{fit_to_terminal, Client}, error::EngineError, raster::Framebuf, record::CastRecorder};
 
 #[derive(Clone)]
 pub struct SshSession {
     clients: Arc<Mutex<HashMap<(Uuid, ChannelId), Client>>>,
-    uuid: Uuid
+    uuid: Uuid,
+    // When set every session is recorded into this directory.
+    record_dir: Option<PathBuf>,
+    // Where new clients' cameras start.
+    pub spawn_camera: Camera
 }
 
 impl Server for SshSession {
@@ -41,82 +46,126 @@ impl Handler for SshSession {
     }
 
     async fn channel_open_session(&mut self, channel: Channel<Msg>, session: &mut Session) -> Result<bool, Self::Error> {
-        self.clients.lock().await
-            .insert((self.uuid, channel.id()), Client::new(session.handle(), self.uuid));
-        
-        session.handle().data(channel.id(), CryptoVec::from(format!("{ANSI_CLEAR_SCREEN}"))).await.unwrap();
-
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_millis(5)).await;
-    
-                /*fb.clear();
-    
-                let elapsed_time = start_time.elapsed().unwrap().as_millis() as f32;
-                
-                let mut model_matrix = rotate(&glm::identity(), elapsed_time / 600.0, &vec3(0.0, 1.0, 0.0));
-                //model_matrix = rotate(&model_matrix, elapsed_time / 1500.0, &vec3(1.0, 0.0, 0.0));
-                model_matrix = scale(&model_matrix, &vec3(1.8, 1.8, 1.8));
-    
-                let vp_matrix = proj_matrix * view_matrix;
-                let normal_matrix = mat4_to_mat3(&model_matrix.try_inverse().unwrap().transpose());
-    
-                fb.draw_model(&models[0], &model_matrix, &vp_matrix, &normal_matrix, &camera_pos, |vertex: &Vertex, c: &mut CharInfo, half: &CharHalf| {
-                    let light_color = vec3(1.0, 1.0, 1.0);
-                    let light_pos = vec3(100.0, 0.0, 50.0);
-                    let light_direction = (light_pos - vec4_to_vec3(&vertex.position)).normalize();
-                    
-                    let ambient_strength = 0.0;
-                    let ambient = ambient_strength * light_color;
-                    
-                    let normal = make_vec3(&vertex.attributes[2..5]);
-                    let diffuse = normal.dot(&light_direction).max(0.0) * light_color;
-    
-                    let tex_coord = make_vec2(&vertex.attributes[0..2]);
-                    let size = 8.0;
-                    let pattern: bool = ((tex_coord[0] * size % 1.0) > 0.5) ^ ((tex_coord[1] * size % 1.0) < 0.5);
-                    let object_color = if pattern { vec3(0.8, 0.85, 1.0) } else { vec3(0.2, 0.6, 0.8) };
-                    let result = (ambient + diffuse).component_mul(&object_color) + vec3(0.2, 0.2, 0.2);
-                    
-                    half_block_shader(c, &half, 
-                        &CharColor { r: (result.x * 255.0) as u8, g: (result.y * 255.0) as u8, b: (result.z * 255.0) as u8 });
-                });
-    
-                let mut str = "\x1b[?25l\x1b[H".to_owned(); // Make cursor invisible and move cursor to beginning.
-                str.push_str(&fb.to_string(&AnsiColorMode::AnsiTrueColor));
-                str.push_str(&format!("Users online: {}\r\n", server_clone.num_sessions().await));
-                
-                server_clone.clone().broadcast_message(str).await;*/
+        let mut client = Client::new(session.handle(), self.uuid, self.spawn_camera.clone());
+
+        if let Some(dir) = &self.record_dir {
+            let path = dir.join(format!("{}-{}.cast", self.uuid, channel.id()));
+            let (width, height) = client.term_size;
+
+            match CastRecorder::create(&path, width, height) {
+                Ok(recorder) => client.recorder = Some(recorder),
+                Err(err) => println!("Failed to start recording {}: {err}", path.display())
             }
-        });
+        }
+
+        self.clients.lock().await.insert((self.uuid, channel.id()), client);
+        
+        // The client may already be gone, that only ends its own session.
+        // Terminals that support it answer the cell size query with a key event, see `Camera::handle_key`.
+        if session.handle().data(channel.id(), CryptoVec::from(format!("{ANSI_CLEAR_SCREEN}{ANSI_QUERY_CELL_SIZE}"))).await.is_err() {
+            self.remove_user(self.uuid, channel.id()).await;
+            return Ok(false);
+        }
 
         Ok(true)
     }
 
+    async fn pty_request(&mut self, channel: ChannelId, term: &str, col_width: u32, row_height: u32, 
+        pix_width: u32, pix_height: u32, _modes: &[(Pty, u32)], _session: &mut Session) -> Result<(), Self::Error> {
+        if let Some(client) = self.clients.lock().await.get_mut(&(self.uuid, channel)) {
+            client.resize(col_width, row_height);
+            client.camera.set_cell_aspect_from_pixels(pix_width, pix_height, col_width, row_height);
+
+            if let Some(recorder) = &mut client.recorder {
+                recorder.set_term(term);
+                let _ = recorder.resize(col_width, row_height);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn window_change_request(&mut self, channel: ChannelId, col_width: u32, row_height: u32, 
+        pix_width: u32, pix_height: u32, session: &mut Session) -> Result<(), Self::Error> {
+        if let Some(client) = self.clients.lock().await.get_mut(&(self.uuid, channel)) {
+            client.resize(col_width, row_height);
+            client.camera.set_cell_aspect_from_pixels(pix_width, pix_height, col_width, row_height);
+
+            // Whatever the old layout left outside the new frame would otherwise stay on screen.
+            session.data(channel, CryptoVec::from(ANSI_CLEAR_SCREEN.to_string()));
+
+            if let Some(recorder) = &mut client.recorder {
+                let _ = recorder.resize(col_width, row_height);
+                let _ = recorder.output(ANSI_CLEAR_SCREEN);
+            }
+        }
+
+        Ok(())
+    }
+
     async fn channel_close(&mut self, channel: ChannelId, _: &mut Session) -> Result<(), Self::Error> {
         self.remove_user(self.uuid, channel).await;
         Ok(())
     }
 
-    async fn data(&mut self, _channel: ChannelId, data: &[u8], _session: &mut Session) -> Result<(), Self::Error> {
+    async fn data(&mut self, channel: ChannelId, data: &[u8], _session: &mut Session) -> Result<(), Self::Error> {
         //let data = CryptoVec::from(format!("You sent: \x1b[38;2;0;255;0m{}\x1b[0m\r\n", String::from_utf8_lossy(data)));
         //session.data(channel, data);
-        println!("User sent: {:#04x?}", data);
+        if let Some(client) = self.clients.lock().await.get_mut(&(self.uuid, channel)) {
+            for key in client.keys.feed(data) {
+                client.camera.handle_key(&key);
+            }
+        }
+
         Ok(())
     }
 }
 
+impl Default for SshSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SshSession {
     pub fn new() -> Self {
         SshSession {
             clients: Arc::new(Mutex::new(HashMap::new())),
-            uuid: Uuid::nil()
+            uuid: Uuid::nil(),
+            record_dir: None,
+            spawn_camera: Camera::default()
+        }
+    }
+
+    // Records every session that connects from now on as an asciicast file in `dir`.
+    pub fn record_all<P: AsRef<Path>>(&mut self, dir: P) -> io::Result<()> {
+        std::fs::create_dir_all(&dir)?;
+        self.record_dir = Some(dir.as_ref().to_owned());
+        Ok(())
+    }
+
+    // Starts recording a single session, replacing any recording already in progress.
+    // Fails with `NotFound` if the session is gone.
+    pub async fn start_recording<P: AsRef<Path>>(&self, uuid: Uuid, channel: ChannelId, path: P) -> io::Result<()> {
+        let mut clients = self.clients.lock().await;
+        let Some(client) = clients.get_mut(&(uuid, channel)) else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No session {uuid} on channel {channel}")));
+        };
+
+        let (width, height) = client.term_size;
+        client.recorder = Some(CastRecorder::create(path, width, height)?);
+        Ok(())
+    }
+
+    pub async fn stop_recording(&self, uuid: Uuid, channel: ChannelId) {
+        if let Some(client) = self.clients.lock().await.get_mut(&(uuid, channel)) {
+            client.recorder = None;
         }
     }
 
-    pub async fn run(&mut self) {
+    pub async fn run(&mut self) -> Result<(), EngineError> {
         let key = russh_keys::load_secret_key(Path::new("key.txt"), None)
-            .expect("Failed to open key.txt (SSH private key)");
+            .map_err(|e| EngineError::asset("key.txt", e))?;
 
         let config = Arc::new(Config {
             inactivity_timeout: Some(Duration::from_secs(5)),
@@ -126,7 +175,13 @@ impl SshSession {
             ..Default::default()
         });
 
-        self.run_on_address(config, ("localhost", 22)).await.unwrap();
+        // Binds first so the message is only printed once the port is actually ours.
+        let socket = TcpListener::bind(("localhost", 22)).await
+            .map_err(|e| io::Error::new(e.kind(), format!("Can't listen on port 22: {e}")))?;
+
+        println!("SSH server started and listening on port 22");
+        self.run_on_socket(config, &socket).await?;
+        Ok(())
     }
 
     pub async fn remove_user(&mut self, uuid: Uuid, channel: ChannelId) {
@@ -136,19 +191,90 @@ impl SshSession {
 
     pub async fn broadcast_message(&mut self, message: String) {
         // Lock the mutex and collect the necessary data
-        let client_data: Vec<(Uuid, ChannelId, Handle)> = {
-            let handles = self.clients.lock().await;
+        let client_data: Vec<(Uuid, ChannelId, Handle, String)> = {
+            let mut handles = self.clients.lock().await;
 
             if handles.is_empty() {
                 return;
             }
 
-            handles.iter().map(|((uuid, channel), client)| (uuid.clone(), channel.clone(), client.handle.clone())).collect()
+            handles.iter_mut().map(|((uuid, channel), client)| {
+                tee(client, &message);
+                (*uuid, *channel, client.handle.clone(), message.clone())
+            }).collect()
+        };
+
+        self.send_all(client_data).await;
+    }
+
+    // Draws every client's frame through its own camera at its own terminal size with `draw`, which
+    // also gets the camera positions of the other players, and sends it with a status line. The
+    // framebuffers are moved out of the client map while rendering on a blocking thread, so input
+    // and connections aren't held up by the lock.
+    pub async fn render_all<F>(&mut self, draw: F)
+    where
+        F: Fn(&mut Framebuf, &Camera, &[Vec3]) + Send + 'static
+    {
+        let frames = {
+            let mut clients = self.clients.lock().await;
+
+            clients.iter_mut()
+                .map(|(key, client)| {
+                    let empty = placeholder(&client.fb);
+                    (*key, std::mem::replace(&mut client.fb, empty), client.camera.clone(), client.term_size)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let status = format!("Users online: {}", frames.len());
+
+        let rendered = tokio::task::spawn_blocking(move || {
+            let positions = frames.iter().map(|(key, _, camera, _)| (*key, camera.position)).collect::<Vec<_>>();
+
+            frames.into_iter().map(|(key, mut fb, camera, term_size)| {
+                let others = positions.iter().filter(|(other, _)| *other != key).map(|(_, p)| *p).collect::<Vec<_>>();
+
+                fit_to_terminal(&mut fb, term_size);
+                draw(&mut fb, &camera, &others);
+
+                let mut str = format!("{ANSI_HIDE_CURSOR}{ANSI_MOVE_CURSOR_TO_START}");
+                str.push_str(&fb.to_string(&AnsiColorMode::AnsiTrueColor));
+                str.push_str(&status);
+
+                (key, fb, str)
+            }).collect::<Vec<_>>()
+        }).await;
+
+        // A panicking frame loses the framebuffers, the placeholders are resized on the next tick.
+        let rendered = match rendered {
+            Ok(rendered) => rendered,
+            Err(err) => {
+                println!("Rendering failed: {err}");
+                return;
+            }
+        };
+
+        let client_data: Vec<(Uuid, ChannelId, Handle, String)> = {
+            let mut clients = self.clients.lock().await;
+
+            rendered.into_iter().filter_map(|(key, fb, str)| {
+                // The client may have left or resized in the meantime.
+                let client = clients.get_mut(&key)?;
+                client.fb = fb;
+                fit_to_terminal(&mut client.fb, client.term_size);
+
+                tee(client, &str);
+                Some((key.0, key.1, client.handle.clone(), str))
+            }).collect()
         };
 
-        // Iterate over the collected data and send the message outside of the lock
-        for (uuid, channel, client) in client_data {
-            if let Err(_) = client.data(channel.clone(), CryptoVec::from(message.clone())).await {
+        self.send_all(client_data).await;
+    }
+
+    // Sends outside of the lock, clients that can't be reached are dropped.
+    async fn send_all(&mut self, client_data: Vec<(Uuid, ChannelId, Handle, String)>) {
+        for (uuid, channel, client, message) in client_data {
+            if client.data(channel, CryptoVec::from(message)).await.is_err() {
                 self.remove_user(uuid, channel).await;
             }
         }
@@ -157,4 +283,19 @@ impl SshSession {
     pub async fn num_sessions(&self) -> usize {
         self.clients.lock().await.len()
     }
-}
\ No newline at end of file
+}
+
+// Tees the stream into the client's recording, if any.
+fn tee(client: &mut Client, message: &str) {
+    if let Some(recorder) = &mut client.recorder {
+        if let Err(err) = recorder.output(message) {
+            println!("Stopped recording after write error: {err}");
+            client.recorder = None;
+        }
+    }
+}
+
+// Empty framebuffer with the same settings as `fb`, so a client whose frame panicked keeps its supersampling.
+fn placeholder(fb: &Framebuf) -> Framebuf {
+    Framebuf { samples: fb.samples, cell_mode: fb.cell_mode, filter: fb.filter, ..Framebuf::new(0, 0) }
+}
@@ -0,0 +1,177 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StencilFunc {
+    Never,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    Always
+}
+
+impl StencilFunc {
+    // Compares the masked reference value against the masked stored value (same order as OpenGL).
+    pub fn test(self, reference: u8, stored: u8) -> bool {
+        match self {
+            StencilFunc::Never => false,
+            StencilFunc::Less => reference < stored,
+            StencilFunc::LessEqual => reference <= stored,
+            StencilFunc::Greater => reference > stored,
+            StencilFunc::GreaterEqual => reference >= stored,
+            StencilFunc::Equal => reference == stored,
+            StencilFunc::NotEqual => reference != stored,
+            StencilFunc::Always => true
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    Increment,
+    IncrementWrap,
+    Decrement,
+    DecrementWrap,
+    Invert
+}
+
+impl StencilOp {
+    pub fn apply(self, stored: u8, reference: u8) -> u8 {
+        match self {
+            StencilOp::Keep => stored,
+            StencilOp::Zero => 0,
+            StencilOp::Replace => reference,
+            StencilOp::Increment => stored.saturating_add(1),
+            StencilOp::IncrementWrap => stored.wrapping_add(1),
+            StencilOp::Decrement => stored.saturating_sub(1),
+            StencilOp::DecrementWrap => stored.wrapping_sub(1),
+            StencilOp::Invert => !stored
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StencilState {
+    pub func: StencilFunc,
+    pub reference: u8,
+    pub read_mask: u8,
+    pub write_mask: u8,
+    // Applied when the stencil test fails.
+    pub fail_op: StencilOp,
+    // Applied when the stencil test passes but the depth test fails.
+    pub depth_fail_op: StencilOp,
+    // Applied when both tests pass.
+    pub pass_op: StencilOp
+}
+
+impl Default for StencilState {
+    fn default() -> Self {
+        Self {
+            func: StencilFunc::Always,
+            reference: 0,
+            read_mask: 0xff,
+            write_mask: 0xff,
+            fail_op: StencilOp::Keep,
+            depth_fail_op: StencilOp::Keep,
+            pass_op: StencilOp::Keep
+        }
+    }
+}
+
+impl StencilState {
+    // Draw everywhere and write `reference` where the draw passes, e.g. to mark a selected object or a portal.
+    pub fn write(reference: u8) -> Self {
+        Self {
+            reference,
+            pass_op: StencilOp::Replace,
+            ..Default::default()
+        }
+    }
+
+    // Only draw where the stored value equals `reference`, leaving the buffer untouched.
+    pub fn equal(reference: u8) -> Self {
+        Self {
+            func: StencilFunc::Equal,
+            reference,
+            ..Default::default()
+        }
+    }
+
+    // Only draw where the stored value differs from `reference`, e.g. outlines and areas outside HUD panels.
+    pub fn not_equal(reference: u8) -> Self {
+        Self {
+            func: StencilFunc::NotEqual,
+            reference,
+            ..Default::default()
+        }
+    }
+
+    pub fn test(&self, stored: u8) -> bool {
+        self.func.test(self.reference & self.read_mask, stored & self.read_mask)
+    }
+
+    pub fn update(&self, stored: &mut u8, op: StencilOp) {
+        let value = op.apply(*stored, self.reference);
+        *stored = (*stored & !self.write_mask) | (value & self.write_mask);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn increment_and_decrement_saturate_or_wrap() {
+        assert_eq!(StencilOp::Increment.apply(255, 0), 255);
+        assert_eq!(StencilOp::IncrementWrap.apply(255, 0), 0);
+        assert_eq!(StencilOp::Decrement.apply(0, 0), 0);
+        assert_eq!(StencilOp::DecrementWrap.apply(0, 0), 255);
+        assert_eq!(StencilOp::Increment.apply(3, 0), 4);
+        assert_eq!(StencilOp::Decrement.apply(3, 0), 2);
+    }
+
+    #[test]
+    fn other_ops() {
+        assert_eq!(StencilOp::Keep.apply(7, 2), 7);
+        assert_eq!(StencilOp::Zero.apply(7, 2), 0);
+        assert_eq!(StencilOp::Replace.apply(7, 2), 2);
+        assert_eq!(StencilOp::Invert.apply(0b1010_0000, 2), 0b0101_1111);
+    }
+
+    #[test]
+    fn compare_functions_put_the_reference_first() {
+        let results = |reference, stored| [
+            StencilFunc::Never, StencilFunc::Less, StencilFunc::LessEqual, StencilFunc::Greater,
+            StencilFunc::GreaterEqual, StencilFunc::Equal, StencilFunc::NotEqual, StencilFunc::Always
+        ].map(|func| func.test(reference, stored));
+
+        assert_eq!(results(1, 2), [false, true, true, false, false, false, true, true]);
+        assert_eq!(results(2, 2), [false, false, true, false, true, true, false, true]);
+        assert_eq!(results(3, 2), [false, false, false, true, true, false, true, true]);
+    }
+
+    #[test]
+    fn read_mask_applies_to_both_sides() {
+        let state = StencilState { read_mask: 0x0f, ..StencilState::equal(0x13) };
+
+        assert!(state.test(0xf3));
+        assert!(!state.test(0x14));
+    }
+
+    #[test]
+    fn write_mask_keeps_unmasked_bits() {
+        let state = StencilState { write_mask: 0x0f, ..StencilState::write(0xab) };
+        let mut stored = 0x50;
+
+        state.update(&mut stored, state.pass_op);
+        assert_eq!(stored, 0x5b);
+
+        // Wrapping only carries within the writable bits.
+        let mut stored = 0x3f;
+        state.update(&mut stored, StencilOp::IncrementWrap);
+        assert_eq!(stored, 0x30);
+    }
+}
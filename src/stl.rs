@@ -0,0 +1,126 @@
+use std::{fs, io, path::Path};
+
+use crate::{error::invalid_data, mesh::{Mesh, NormalMode}};
+
+// Loads an ASCII or binary STL file. STL has no shared vertices, so every triangle gets
+// its own three vertices with the flat face normal.
+pub fn load_stl<P: AsRef<Path>>(path: P) -> io::Result<Mesh> {
+    let name = path.as_ref().file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut mesh = parse_stl(&fs::read(path)?)?;
+
+    mesh.name = name;
+    Ok(mesh)
+}
+
+pub fn parse_stl(bytes: &[u8]) -> io::Result<Mesh> {
+    // Binary files may also start with "solid", their size is the reliable way to tell them apart.
+    let binary_size = bytes.get(80..84)
+        .map(|count| 84 + 50 * u32::from_le_bytes(count.try_into().unwrap()) as usize);
+
+    let positions = if binary_size == Some(bytes.len()) || !bytes.starts_with(b"solid") {
+        parse_binary(bytes)?
+    } else {
+        parse_ascii(bytes)?
+    };
+
+    let count = positions.len() / 3;
+    let mut mesh = Mesh::new(String::new(), positions, Vec::new(), Vec::new(), (0..count as u32).collect());
+
+    // Facet normals in files are often zero or wrong, so they're computed from the winding instead.
+    mesh.generate_missing(NormalMode::Flat);
+    mesh.validate().map_err(|e| invalid_data(e.to_string()))?;
+    Ok(mesh)
+}
+
+fn parse_binary(bytes: &[u8]) -> io::Result<Vec<f32>> {
+    let count = bytes.get(80..84)
+        .map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize)
+        .ok_or_else(|| invalid_data("STL file is too short".to_owned()))?;
+
+    if bytes.len() < 84 + 50 * count {
+        return Err(invalid_data(format!("STL file has {} bytes, {count} triangles need {}", bytes.len(), 84 + 50 * count)));
+    }
+
+    // Each triangle is a normal, three vertices and two attribute bytes.
+    Ok(bytes[84..84 + 50 * count].chunks(50)
+        .flat_map(|triangle| triangle[12..48].chunks(4).map(|f| f32::from_le_bytes(f.try_into().unwrap())))
+        .collect())
+}
+
+fn parse_ascii(bytes: &[u8]) -> io::Result<Vec<f32>> {
+    let text = std::str::from_utf8(bytes).map_err(|e| invalid_data(format!("ASCII STL is not valid UTF-8: {e}")))?;
+    let mut tokens = text.split_ascii_whitespace();
+    let mut positions = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        if token != "vertex" {
+            continue;
+        }
+
+        for _ in 0..3 {
+            let value = tokens.next().ok_or_else(|| invalid_data("Unexpected end of STL file".to_owned()))?;
+            positions.push(value.parse::<f32>().map_err(|_| invalid_data(format!("Invalid STL coordinate '{value}'")))?);
+        }
+    }
+
+    if positions.len() % 9 != 0 {
+        return Err(invalid_data(format!("STL file has {} vertices, not a multiple of 3", positions.len() / 3)));
+    }
+
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    fn binary_stl(triangles: u32, written: usize) -> Vec<u8> {
+        // Headers starting with "solid" are allowed and trip up naive format detection.
+        let mut bytes = b"solid binary".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend(triangles.to_le_bytes());
+
+        for _ in 0..written {
+            bytes.extend([0u8; 12]);
+            bytes.extend(TRIANGLE.iter().flatten().flat_map(|f| f.to_le_bytes()));
+            bytes.extend([0u8; 2]);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn ascii() {
+        let text = "solid t\n facet normal 0 0 0\n  outer loop\n   vertex 0 0 0\n   vertex 1 0 0\n   vertex 0 1 0\n  endloop\n endfacet\nendsolid t\n";
+        let mesh = parse_stl(text.as_bytes()).unwrap();
+
+        assert_eq!(mesh.positions, TRIANGLE.concat());
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        // The zero facet normal is replaced by the one from the winding.
+        assert_eq!(&mesh.normals[..3], &[0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn binary() {
+        let mesh = parse_stl(&binary_stl(2, 2)).unwrap();
+
+        assert_eq!(mesh.positions, [TRIANGLE.concat(), TRIANGLE.concat()].concat());
+        assert_eq!(mesh.indices, [0, 1, 2, 3, 4, 5]);
+        assert!(mesh.validate().is_ok());
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let err = parse_stl(&binary_stl(3, 2)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        assert!(parse_stl(&[0; 40]).is_err());
+    }
+
+    #[test]
+    fn ascii_with_missing_coordinates() {
+        assert!(parse_stl(b"solid t\n vertex 0 0 0\n vertex 1 0\nendsolid t\n").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use std::{fs::File, io::{self, BufReader, BufWriter, Write}, path::Path};
+
+use glm::Vec2;
+use png::{BitDepth, ColorType, Decoder, DecodingError, Encoder, EncodingError, Transformations};
+
+use crate::char::CharColor;
+
+#[derive(Debug, Clone)]
+pub struct Texture {
+    pub w: usize,
+    pub h: usize,
+    pub pixels: Vec<CharColor>
+}
+
+impl Texture {
+    pub fn new(w: usize, h: usize, pixels: Vec<CharColor>) -> Self {
+        debug_assert!(pixels.len() == w * h);
+        Self { w, h, pixels }
+    }
+
+    pub fn from_color(color: CharColor) -> Self {
+        Self::new(1, 1, vec![color])
+    }
+
+    // Loads a PNG, dropping the alpha channel.
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Self, DecodingError> {
+        Self::decode_png(BufReader::new(File::open(path)?))
+    }
+
+    pub fn decode_png<R: std::io::Read>(reader: R) -> Result<Self, DecodingError> {
+        let mut decoder = Decoder::new(reader);
+        decoder.set_transformations(Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+
+        let channels = match info.color_type {
+            ColorType::Grayscale => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+            // Expanded by the normalize transformation.
+            ColorType::Indexed => 3
+        };
+
+        let pixels = buf[..info.buffer_size()].chunks(channels)
+            .map(|p| match channels {
+                1 | 2 => CharColor { r: p[0], g: p[0], b: p[0] },
+                _ => CharColor { r: p[0], g: p[1], b: p[2] }
+            })
+            .collect();
+
+        Ok(Self::new(info.width as usize, info.height as usize, pixels))
+    }
+
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> Result<(), EncodingError> {
+        let mut encoder = Encoder::new(BufWriter::new(File::create(path)?), self.w as u32, self.h as u32);
+        encoder.set_color(ColorType::Rgb);
+        encoder.set_depth(BitDepth::Eight);
+
+        encoder.write_header()?.write_image_data(&self.to_rgb_bytes())
+    }
+
+    // Binary PPM (P6), readable by most image viewers without any dependencies.
+    pub fn write_ppm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "P6\n{} {}\n255\n", self.w, self.h)?;
+        writer.write_all(&self.to_rgb_bytes())?;
+        writer.flush()
+    }
+
+    pub fn to_rgb_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|p| [p.r, p.g, p.b]).collect()
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> CharColor {
+        self.pixels[y * self.w + x]
+    }
+
+    // Nearest neighbour lookup with repeating texture coordinates, (0, 0) is the top left corner.
+    pub fn sample(&self, uv: &Vec2) -> CharColor {
+        let x = (uv.x.rem_euclid(1.0) * self.w as f32) as usize;
+        let y = (uv.y.rem_euclid(1.0) * self.h as f32) as usize;
+
+        self.get(x.min(self.w - 1), y.min(self.h - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> Texture {
+        let pixels = (0..6).map(|i| CharColor { r: i * 40, g: 255 - i * 40, b: if i % 2 == 0 { 0 } else { 255 } }).collect();
+        Texture::new(3, 2, pixels)
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("terminal-engine-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn ppm_has_header_and_raw_rgb() {
+        let path = temp_path("checker.ppm");
+        checker().write_ppm(&path).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(&bytes[header.len()..], &checker().to_rgb_bytes()[..]);
+    }
+
+    #[test]
+    fn png_round_trips() {
+        let path = temp_path("checker.png");
+        checker().write_png(&path).unwrap();
+
+        let loaded = Texture::load_png(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!((loaded.w, loaded.h), (3, 2));
+        assert_eq!(loaded.to_rgb_bytes(), checker().to_rgb_bytes());
+    }
+}
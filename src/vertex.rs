@@ -3,7 +3,7 @@ use std::{iter::zip, ops::{AddAssign, Div}};
 use glm::Vec4;
 
 pub fn lerp(a: f32, b: f32, amount: f32) -> f32 {
-    return amount * b + (1.0 - amount) * a;
+    amount * b + (1.0 - amount) * a
 }
 
 #[derive(Debug, Clone)]